@@ -0,0 +1,71 @@
+/// Crates whose resolved versions are reported, since they do the work being measured.
+const BENCHMARKED_CRATES: &[&str] = &[
+    "fdeflate",
+    "image",
+    "image-webp",
+    "lcms2",
+    "miniz_oxide",
+    "moxcms",
+    "mtpng",
+    "png",
+    "qcms",
+    "qoi",
+    "rav1e",
+    "webp",
+    "zune-jpeg",
+    "zune-png",
+    "zune-qoi",
+];
+
+fn main() {
+    // Record how this binary was compiled so it can refuse to benchmark unoptimized builds and
+    // report its codegen settings alongside the results.
+    println!(
+        "cargo:rustc-env=CORPUS_BENCH_OPT_LEVEL={}",
+        std::env::var("OPT_LEVEL").unwrap()
+    );
+    println!(
+        "cargo:rustc-env=CORPUS_BENCH_RUSTFLAGS={}",
+        std::env::var("CARGO_ENCODED_RUSTFLAGS")
+            .unwrap_or_default()
+            .replace('\x1f', " ")
+    );
+
+    // Cargo.lock is written before build scripts run, so it holds the versions being compiled.
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let mut versions = Vec::new();
+    let mut name = None;
+    for line in lock.lines() {
+        if let Some(value) = line.strip_prefix("name = ") {
+            name = Some(value.trim_matches('"'));
+        } else if let (Some(value), Some(name)) = (line.strip_prefix("version = "), name.take()) {
+            if BENCHMARKED_CRATES.contains(&name) {
+                versions.push(format!("{} {}", name, value.trim_matches('"')));
+            }
+        }
+    }
+    println!(
+        "cargo:rustc-env=CORPUS_BENCH_CRATE_VERSIONS={}",
+        versions.join(", ")
+    );
+
+    // The commit the binary was built from, so that a corpus lockfile can say exactly which
+    // version of the tool wrote it.
+    let git_hash = std::process::Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        .unwrap_or_else(|| "unknown commit".to_owned());
+    println!("cargo:rustc-env=CORPUS_BENCH_GIT_HASH={}", git_hash);
+    for path in [".git/HEAD", ".git/logs/HEAD"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-env-changed=CARGO_ENCODED_RUSTFLAGS");
+}
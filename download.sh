@@ -1,7 +1,28 @@
+# Pass --from-tar <path> or set QOI_BENCHMARK_TAR to install the QOI benchmark suite from a local
+# copy of qoi_benchmark_suite.tar instead of downloading it.
+tar_path="$QOI_BENCHMARK_TAR"
+if [ "$1" = "--from-tar" ]; then
+    tar_path="$2"
+fi
+case "$tar_path" in
+    "" | /*) ;;
+    *) tar_path="$PWD/$tar_path" ;;
+esac
+
 mkdir -p corpus
 cd corpus
 
 mkdir -p qoi_benchmark_suite
-wget https://qoiformat.org/benchmark/qoi_benchmark_suite.tar
-tar -xf qoi_benchmark_suite.tar -C qoi_benchmark_suite
-
+if [ -z "$tar_path" ]; then
+    tar_path=qoi_benchmark_suite.tar
+    # An archive that can't be listed was truncated by an interrupted download.
+    if [ -f "$tar_path" ] && ! tar -tf "$tar_path" > /dev/null 2>&1; then
+        rm "$tar_path"
+    fi
+    if [ ! -f "$tar_path" ]; then
+        wget -O "$tar_path.part" https://qoiformat.org/benchmark/qoi_benchmark_suite.tar || exit 1
+        mv "$tar_path.part" "$tar_path"
+    fi
+fi
+# Every entry is extracted again, which replaces any files left truncated by an interrupted run.
+tar -xf "$tar_path" -C qoi_benchmark_suite
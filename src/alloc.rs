@@ -0,0 +1,97 @@
+//! A global allocator that counts allocations, enabled by the `count-allocations` feature. Memory
+//! allocated by C libraries doesn't go through it and so isn't counted.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::results::AllocationStats;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static REALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn grow(bytes: usize) {
+    let live = LIVE_BYTES.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK_BYTES.fetch_max(live, Ordering::Relaxed);
+}
+
+fn shrink(bytes: usize) {
+    LIVE_BYTES.fetch_sub(bytes, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        shrink(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            REALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            if new_size > layout.size() {
+                grow(new_size - layout.size());
+            } else {
+                shrink(layout.size() - new_size);
+            }
+        }
+        new_ptr
+    }
+}
+
+/// The allocator's counters at the start of a timed region.
+pub struct Snapshot {
+    allocations: u64,
+    reallocations: u64,
+    live_bytes: usize,
+}
+
+impl Snapshot {
+    /// Takes a snapshot and resets the peak, so that it only reflects the region that follows.
+    pub fn take() -> Self {
+        let live_bytes = LIVE_BYTES.load(Ordering::Relaxed);
+        PEAK_BYTES.store(live_bytes, Ordering::Relaxed);
+        Self {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            reallocations: REALLOCATIONS.load(Ordering::Relaxed),
+            live_bytes,
+        }
+    }
+
+    /// Allocations made since the snapshot was taken.
+    pub fn since(&self) -> AllocationStats {
+        AllocationStats {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - self.allocations,
+            reallocations: REALLOCATIONS.load(Ordering::Relaxed) - self.reallocations,
+            peak_bytes: PEAK_BYTES
+                .load(Ordering::Relaxed)
+                .saturating_sub(self.live_bytes) as u64,
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! Reading corpus files straight out of a tar archive instead of an extracted directory, for
+//! machines without the disk space to hold both.
+//!
+//! The archive is indexed once, and each file is then read from its offset in the archive as it
+//! is needed. Gzipped archives can't be seeked into, so they are decompressed into memory instead.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+const BLOCK_SIZE: u64 = 512;
+
+/// The first bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The archive that corpus files are read from, if any
+static ARCHIVE: OnceLock<Archive> = OnceLock::new();
+
+struct Archive {
+    path: PathBuf,
+    /// The decompressed contents of a compressed archive, which offsets refer to instead of the
+    /// file at `path`
+    contents: Option<Vec<u8>>,
+    /// Offset and length of every regular file, keyed by the path it would be extracted to
+    entries: BTreeMap<PathBuf, (u64, u64)>,
+}
+
+/// A buffered reader of a corpus file
+pub trait Source: BufRead + Seek {}
+
+impl<R: BufRead + Seek> Source for R {}
+
+/// Reads the range of a file holding one archive entry, as if it were the whole file.
+struct Entry {
+    file: File,
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+impl Read for Entry {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let max = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let read = self.file.read(&mut buf[..max])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for Entry {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start"))?;
+        self.file.seek(SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+/// Parses a NUL or space terminated octal number from a tar header field.
+fn parse_octal(field: &[u8]) -> u64 {
+    field
+        .iter()
+        .skip_while(|&&b| b == b' ')
+        .take_while(|&&b| (b'0'..=b'7').contains(&b))
+        .fold(0, |n, &b| n * 8 + (b - b'0') as u64)
+}
+
+/// Parses a NUL terminated string from a tar header field.
+fn parse_name(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// The path and size that a pax extended header overrides for the next entry, if it sets them
+#[derive(Default)]
+struct PaxOverrides {
+    path: Option<String>,
+    size: Option<u64>,
+}
+
+/// Parses the records of a pax extended header, each of the form `<length> <key>=<value>\n`.
+fn parse_pax(data: &[u8]) -> PaxOverrides {
+    let mut overrides = PaxOverrides::default();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let Some(len) = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|len| len.parse::<usize>().ok())
+            .filter(|&len| len > space && len <= rest.len())
+        else {
+            break;
+        };
+        let record = &rest[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(equals) = record.iter().position(|&b| b == b'=') {
+            let value = String::from_utf8_lossy(&record[equals + 1..]);
+            match &record[..equals] {
+                b"path" => overrides.path = Some(value.into_owned()),
+                b"size" => overrides.size = value.parse().ok(),
+                _ => {}
+            }
+        }
+        rest = &rest[len..];
+    }
+    overrides
+}
+
+/// Indexes the tar archive at `path` so that later reads of files under `directory` come from it,
+/// as if it had been extracted there. A gzipped archive is decompressed into memory first.
+pub fn open(path: &Path, directory: &Path) {
+    let mut file = File::open(path).unwrap();
+    let mut magic = [0; 2];
+    let compressed = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+    file.rewind().unwrap();
+
+    let (entries, contents) = if compressed {
+        let mut contents = Vec::new();
+        flate2::read::MultiGzDecoder::new(BufReader::new(file))
+            .read_to_end(&mut contents)
+            .unwrap();
+        (index(Cursor::new(&contents), directory), Some(contents))
+    } else {
+        (index(file, directory), None)
+    };
+
+    info!("Reading {} files from {}", entries.len(), path.display());
+    let archive = Archive {
+        path: path.to_owned(),
+        contents,
+        entries,
+    };
+    assert!(ARCHIVE.set(archive).is_ok(), "an archive is already open");
+}
+
+/// Whether the open archive is held in memory rather than read from disk.
+pub fn in_memory() -> bool {
+    ARCHIVE
+        .get()
+        .is_some_and(|archive| archive.contents.is_some())
+}
+
+/// Finds the offset and length of every regular file in a tar archive, keyed by the path under
+/// `directory` it would be extracted to.
+fn index<R: Read + Seek>(mut file: R, directory: &Path) -> BTreeMap<PathBuf, (u64, u64)> {
+    let mut entries = BTreeMap::new();
+    let mut header = [0; BLOCK_SIZE as usize];
+    let mut offset = 0;
+    let mut long_name = None;
+    let mut pax = PaxOverrides::default();
+
+    while file.read_exact(&mut header).is_ok() && header.iter().any(|&b| b != 0) {
+        let size = match header[156] {
+            b'x' => parse_octal(&header[124..136]),
+            _ => pax
+                .size
+                .take()
+                .unwrap_or_else(|| parse_octal(&header[124..136])),
+        };
+        let data_offset = offset + BLOCK_SIZE;
+        match header[156] {
+            // GNU long names are stored as the data of a preceding entry.
+            b'L' => {
+                let mut name = vec![0; size as usize];
+                file.read_exact(&mut name).unwrap();
+                long_name = Some(parse_name(&name));
+            }
+            // So are pax extended headers, which override fields of the next entry.
+            b'x' => {
+                let mut data = vec![0; size as usize];
+                file.read_exact(&mut data).unwrap();
+                pax = parse_pax(&data);
+            }
+            b'0' | 0 => {
+                let name = pax.path.take().or(long_name.take()).unwrap_or_else(|| {
+                    let prefix = parse_name(&header[345..500]);
+                    let name = parse_name(&header[..100]);
+                    if prefix.is_empty() {
+                        name
+                    } else {
+                        format!("{}/{}", prefix, name)
+                    }
+                });
+                let name = name.trim_start_matches("./");
+                entries.insert(directory.join(name), (data_offset, size));
+            }
+            // Global pax headers apply to every later entry, but only set metadata that isn't
+            // needed to find files.
+            b'g' => {}
+            _ => {
+                long_name = None;
+                pax = PaxOverrides::default();
+            }
+        }
+        offset = data_offset + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+        file.seek(SeekFrom::Start(offset)).unwrap();
+    }
+    entries
+}
+
+/// Lists the files in the open archive under `directory`, or `None` if no archive is open.
+pub fn files(directory: &Path) -> Option<Vec<PathBuf>> {
+    let archive = ARCHIVE.get()?;
+    Some(
+        archive
+            .entries
+            .keys()
+            .filter(|path| path.starts_with(directory))
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Reads a corpus file from the open archive, or from disk if it isn't in one.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    if let Some(contents) = in_memory_contents(path) {
+        return Ok(contents.to_vec());
+    }
+    let (source, offset, len) = location(path);
+    if source == path {
+        return std::fs::read(path);
+    }
+    let mut file = File::open(source)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = vec![0; len as usize];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Opens a corpus file for reading only as much of it as is needed, such as its header.
+pub fn open_file(path: &Path) -> io::Result<Box<dyn Source>> {
+    if let Some(contents) = in_memory_contents(path) {
+        return Ok(Box::new(Cursor::new(contents)));
+    }
+    let (source, start, len) = location(path);
+    if source == path {
+        return Ok(Box::new(BufReader::new(File::open(path)?)));
+    }
+    let mut file = File::open(source)?;
+    file.seek(SeekFrom::Start(start))?;
+    Ok(Box::new(BufReader::new(Entry {
+        file,
+        start,
+        len,
+        position: 0,
+    })))
+}
+
+/// The contents of a corpus file, if it is in an archive held in memory.
+fn in_memory_contents(path: &Path) -> Option<&'static [u8]> {
+    let archive = ARCHIVE.get()?;
+    let contents = archive.contents.as_ref()?;
+    let &(offset, len) = archive.entries.get(path)?;
+    contents.get(offset as usize..(offset + len) as usize)
+}
+
+/// The size of a corpus file in the open archive, or on disk if it isn't in one.
+pub fn len(path: &Path) -> io::Result<u64> {
+    match ARCHIVE.get().and_then(|archive| archive.entries.get(path)) {
+        Some(&(_, len)) => Ok(len),
+        None => Ok(std::fs::metadata(path)?.len()),
+    }
+}
+
+/// The file a corpus file is stored in, with the offset and length of its contents. A length of
+/// zero covers the rest of the file. For an archive held in memory, the offset is into its
+/// decompressed contents rather than the file.
+pub fn location(path: &Path) -> (&Path, u64, u64) {
+    match ARCHIVE
+        .get()
+        .and_then(|archive| Some((archive, archive.entries.get(path)?)))
+    {
+        Some((archive, &(offset, len))) => (&archive.path, offset, len),
+        None => (path, 0, 0),
+    }
+}
+
+/// Decodes a corpus image, guessing its format from its contents.
+pub fn open_image(path: &Path) -> image::ImageResult<image::DynamicImage> {
+    image::load_from_memory(&read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Appends a tar entry of type `kind` holding `data` to `tar`, with its size field set to
+    /// `size` rather than the data's length, so that pax overrides can be tested.
+    fn append(tar: &mut Vec<u8>, name: &str, kind: u8, size: usize, data: &[u8]) {
+        let mut header = [0; BLOCK_SIZE as usize];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+        header[156] = kind;
+        tar.extend(header);
+        tar.extend(data);
+        tar.resize(
+            tar.len().div_ceil(BLOCK_SIZE as usize) * BLOCK_SIZE as usize,
+            0,
+        );
+    }
+
+    /// A pax extended header record, which starts with its own length in bytes.
+    fn pax_record(key: &str, value: &str) -> String {
+        let body = format!(" {}={}\n", key, value);
+        let mut len = body.len() + 1;
+        while format!("{}{}", len, body).len() != len {
+            len += 1;
+        }
+        format!("{}{}", len, body)
+    }
+
+    #[test]
+    fn pax_records_override_path_and_size() {
+        let data = pax_record("mtime", "1700000000.5")
+            + &pax_record("path", "a directory/with a long name.png")
+            + &pax_record("size", "12345");
+        let overrides = parse_pax(data.as_bytes());
+        assert_eq!(
+            overrides.path.as_deref(),
+            Some("a directory/with a long name.png")
+        );
+        assert_eq!(overrides.size, Some(12345));
+
+        // Parsing stops at a record whose length doesn't fit.
+        let overrides = parse_pax(b"99 path=truncated\n");
+        assert_eq!(overrides.path, None);
+    }
+
+    #[test]
+    fn index_finds_every_regular_file() {
+        let mut tar = Vec::new();
+        append(&mut tar, "./suite/", b'5', 0, &[]);
+        append(&mut tar, "./suite/plain.png", b'0', 3, b"abc");
+        let long_name = format!("suite/{}.png", "x".repeat(120));
+        append(
+            &mut tar,
+            "././@LongLink",
+            b'L',
+            long_name.len(),
+            long_name.as_bytes(),
+        );
+        append(&mut tar, "suite/truncated", b'0', 5, b"hello");
+        append(&mut tar, "pax_global_header", b'g', 0, &[]);
+        let pax = pax_record("path", "suite/pax name.png") + &pax_record("size", "600");
+        append(&mut tar, "PaxHeaders/x", b'x', pax.len(), pax.as_bytes());
+        append(&mut tar, "suite/pax", b'0', 0, &[7; 600]);
+        append(&mut tar, "suite/after.png", 0, 1, b"z");
+        tar.extend([0; 2 * BLOCK_SIZE as usize]);
+
+        let entries = index(Cursor::new(&tar), Path::new("corpus"));
+        let found: Vec<(&Path, (u64, u64))> = entries
+            .iter()
+            .map(|(path, &location)| (path.as_path(), location))
+            .collect();
+        let long_path = Path::new("corpus").join(&long_name);
+        let mut expected = vec![
+            (Path::new("corpus/suite/plain.png"), (1024, 3)),
+            (long_path.as_path(), (3072, 5)),
+            (Path::new("corpus/suite/pax name.png"), (5632, 600)),
+            (Path::new("corpus/suite/after.png"), (7168, 1)),
+        ];
+        expected.sort();
+        assert_eq!(found, expected);
+        for (path, (offset, len)) in expected {
+            let range = offset as usize..(offset + len) as usize;
+            assert!(
+                tar[range].iter().all(|&b| b != 0),
+                "{} points at padding",
+                path.display()
+            );
+        }
+    }
+}
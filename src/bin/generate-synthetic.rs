@@ -0,0 +1,360 @@
+//! Generates images with controlled content, so that performance can be compared across kinds of
+//! image rather than only averaged over a real-world corpus.
+
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueEnum};
+use image::{DynamicImage, Rgb, Rgb32FImage};
+use rand::prelude::*;
+
+#[derive(Parser, Debug)]
+#[command(about = "Generate a corpus of synthetic images with controlled content")]
+struct Args {
+    /// Width and height of the generated images
+    #[arg(long = "size", default_values_t = [256, 1024])]
+    sizes: Vec<u32>,
+
+    /// Bit depths to save each image at
+    #[arg(long = "bit-depth", value_enum, default_values = ["8", "16"])]
+    bit_depths: Vec<BitDepth>,
+
+    /// Kinds of content to generate, all of them if not given
+    #[arg(long = "content", value_enum)]
+    contents: Vec<Content>,
+
+    /// Seed for the random number generator
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Directory to write images into, with one subdirectory per kind of content
+    #[arg(long, default_value = "corpus/synthetic")]
+    output: PathBuf,
+
+    /// Also write sequences of this many 8-bit animation frames at each size
+    #[arg(long, default_value_t = 0)]
+    frames: u32,
+
+    /// Directory to write frame sequences into, with one subdirectory per sequence
+    #[arg(long, default_value = "corpus/frames")]
+    frames_output: PathBuf,
+
+    /// Also write each image as 32-bit float OpenEXR with values beyond the 0 to 1 range
+    #[arg(long)]
+    hdr: bool,
+
+    /// Directory to write high dynamic range images into, with one subdirectory per kind of content
+    #[arg(long, default_value = "corpus/hdr")]
+    hdr_output: PathBuf,
+}
+
+/// Brightest value in the high dynamic range images, relative to a white of 1.0
+const HDR_PEAK: f32 = 16.0;
+
+/// How far the view moves between consecutive frames of a sequence, in pixels
+const FRAME_STEP: u32 = 4;
+
+/// The bit depth per channel of the generated images
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum BitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+/// The kind of content to generate
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Content {
+    /// Smooth horizontal, vertical and diagonal color ramps
+    Gradient,
+    /// Multi-octave Perlin noise in each channel
+    Perlin,
+    /// A single color
+    Flat,
+    /// Smooth photo-like content dithered to a 256 color palette
+    Dithered,
+    /// Dark glyphs on a light background, like a screenshot of text
+    Text,
+    /// Uniformly random pixels
+    Noise,
+}
+
+fn main() {
+    let args = Args::parse();
+    let contents = if args.contents.is_empty() {
+        Content::value_variants().to_vec()
+    } else {
+        args.contents.clone()
+    };
+
+    let needed = estimated_size(&args, contents.len());
+    if let Some(free) = free_space(&args.output) {
+        if needed > free {
+            eprintln!(
+                "Generating the corpus may need up to {} MiB, but only {} MiB is free in {}",
+                needed >> 20,
+                free >> 20,
+                args.output.display()
+            );
+            std::process::exit(1);
+        }
+    }
+
+    if args.frames > 0 {
+        for &size in &args.sizes {
+            write_frames(&args, size);
+        }
+    }
+
+    for content in contents {
+        let name = content.to_possible_value().unwrap().get_name().to_owned();
+        let directory = args.output.join(&name);
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for &size in &args.sizes {
+            let mut rng = StdRng::seed_from_u64(args.seed ^ (content as u64) << 32 ^ size as u64);
+            let image = generate(content, size, &mut rng);
+            if args.hdr {
+                let directory = args.hdr_output.join(&name);
+                std::fs::create_dir_all(&directory).unwrap();
+                let path = directory.join(format!("{name}_{size}x{size}.exr"));
+                DynamicImage::ImageRgb32F(expand_range(&image))
+                    .save(&path)
+                    .unwrap();
+                println!("Wrote {}", path.display());
+            }
+            let image = DynamicImage::ImageRgb32F(image);
+
+            for &bit_depth in &args.bit_depths {
+                let (image, bits) = match bit_depth {
+                    BitDepth::Eight => (DynamicImage::from(image.to_rgb8()), 8),
+                    BitDepth::Sixteen => (DynamicImage::from(image.to_rgb16()), 16),
+                };
+                let path = directory.join(format!("{name}_{size}x{size}_{bits}bit.png"));
+                image.save(&path).unwrap();
+                println!("Wrote {}", path.display());
+            }
+        }
+    }
+}
+
+/// Upper bound on the number of bytes the images will take up, assuming none of them compress.
+fn estimated_size(args: &Args, contents: usize) -> u64 {
+    let contents = contents as u64;
+    let mut total = 0;
+    for &size in &args.sizes {
+        let samples = size as u64 * size as u64 * 3;
+        for &bit_depth in &args.bit_depths {
+            let bytes_per_sample = match bit_depth {
+                BitDepth::Eight => 1,
+                BitDepth::Sixteen => 2,
+            };
+            total += contents * samples * bytes_per_sample;
+        }
+        if args.hdr {
+            total += contents * samples * 4;
+        }
+        total += 2 * args.frames as u64 * samples;
+    }
+    total
+}
+
+/// Bytes available on the filesystem holding `path`, or the closest of its ancestors that exists.
+#[cfg(target_os = "linux")]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("."));
+    let existing = CString::new(existing.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: the path is NUL terminated, and `stat` is only read if the call succeeded.
+    let stat = unsafe {
+        if libc::statvfs(existing.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Writes two sequences of frames: a pan across Perlin noise, like a camera moving over a scene,
+/// and a vertical scroll through text, like a screen recording.
+fn write_frames(args: &Args, size: u32) {
+    let travel = (args.frames - 1) * FRAME_STEP;
+    let mut rng = StdRng::seed_from_u64(args.seed ^ size as u64);
+    let sequences = [
+        ("pan", perlin_image(size + travel, size, &mut rng), (1, 0)),
+        ("scroll", text(size, size + travel, &mut rng), (0, 1)),
+    ];
+
+    for (name, scene, (dx, dy)) in sequences {
+        let directory = args.frames_output.join(format!("{name}_{size}x{size}"));
+        std::fs::create_dir_all(&directory).unwrap();
+        for frame in 0..args.frames {
+            let (x, y) = (dx * frame * FRAME_STEP, dy * frame * FRAME_STEP);
+            let view = image::imageops::crop_imm(&scene, x, y, size, size).to_image();
+            let path = directory.join(format!("frame_{frame:04}.png"));
+            DynamicImage::ImageRgb32F(view)
+                .to_rgb8()
+                .save(&path)
+                .unwrap();
+        }
+        println!("Wrote {} frames to {}", args.frames, directory.display());
+    }
+}
+
+/// Stretches an image's 0 to 1 values into a high dynamic range from 0 to `HDR_PEAK`, with most
+/// of the image staying in the normal range and only the brightest parts exceeding it.
+fn expand_range(image: &Rgb32FImage) -> Rgb32FImage {
+    let mut image = image.clone();
+    for value in image.iter_mut() {
+        *value = value.powi(4) * HDR_PEAK;
+    }
+    image
+}
+
+fn generate(content: Content, size: u32, rng: &mut StdRng) -> Rgb32FImage {
+    match content {
+        Content::Gradient => Rgb32FImage::from_fn(size, size, |x, y| {
+            let (x, y) = (x as f32 / size as f32, y as f32 / size as f32);
+            Rgb([x, y, (x + y) / 2.0])
+        }),
+        Content::Perlin => perlin_image(size, size, rng),
+        Content::Flat => Rgb32FImage::from_pixel(size, size, Rgb(rng.gen())),
+        Content::Dithered => {
+            let mut image = perlin_image(size, size, rng);
+            dither(&mut image, [8, 8, 4]);
+            image
+        }
+        Content::Text => text(size, size, rng),
+        Content::Noise => Rgb32FImage::from_fn(size, size, |_, _| Rgb(rng.gen())),
+    }
+}
+
+/// Improved Perlin noise over a randomly shuffled permutation table.
+struct Perlin {
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    fn new(rng: &mut StdRng) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        table.shuffle(rng);
+        Self {
+            permutation: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    /// Noise at `(x, y)`, roughly in the range [-1, 1].
+    fn noise(&self, x: f32, y: f32) -> f32 {
+        let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |t: f32, a: f32, b: f32| a + t * (b - a);
+        let grad = |hash: u8, x: f32, y: f32| match hash & 3 {
+            0 => x + y,
+            1 => -x + y,
+            2 => x - y,
+            _ => -x - y,
+        };
+        let p = |i: usize| self.permutation[i] as usize;
+
+        let (xi, yi) = (x.floor() as usize & 255, y.floor() as usize & 255);
+        let (xf, yf) = (x - x.floor(), y - y.floor());
+        let (u, v) = (fade(xf), fade(yf));
+
+        let aa = self.permutation[p(xi) + yi];
+        let ab = self.permutation[p(xi) + yi + 1];
+        let ba = self.permutation[p(xi + 1) + yi];
+        let bb = self.permutation[p(xi + 1) + yi + 1];
+
+        lerp(
+            v,
+            lerp(u, grad(aa, xf, yf), grad(ba, xf - 1.0, yf)),
+            lerp(u, grad(ab, xf, yf - 1.0), grad(bb, xf - 1.0, yf - 1.0)),
+        )
+    }
+
+    /// Sum of several octaves of noise, mapped to the range [0, 1].
+    fn fractal(&self, x: f32, y: f32) -> f32 {
+        let mut total = 0.0;
+        let (mut frequency, mut amplitude) = (1.0, 0.5);
+        for _ in 0..5 {
+            total += amplitude * self.noise(x * frequency, y * frequency);
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+        (total * 0.5 + 0.5).clamp(0.0, 1.0)
+    }
+}
+
+fn perlin_image(width: u32, height: u32, rng: &mut StdRng) -> Rgb32FImage {
+    let channels: [Perlin; 3] = std::array::from_fn(|_| Perlin::new(rng));
+    let scale = 8.0 / height as f32;
+    Rgb32FImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as f32 * scale, y as f32 * scale);
+        Rgb(std::array::from_fn(|c| channels[c].fractal(x, y)))
+    })
+}
+
+/// Floyd-Steinberg dithers each channel to the given number of evenly spaced levels.
+fn dither(image: &mut Rgb32FImage, levels: [u32; 3]) {
+    let (width, height) = image.dimensions();
+    for y in 0..height {
+        for x in 0..width {
+            let old = image.get_pixel(x, y).0;
+            let new: [f32; 3] = std::array::from_fn(|c| {
+                let steps = (levels[c] - 1) as f32;
+                (old[c].clamp(0.0, 1.0) * steps).round() / steps
+            });
+            image.put_pixel(x, y, Rgb(new));
+
+            for (dx, dy, weight) in [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)] {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let pixel = image.get_pixel_mut(nx as u32, ny as u32);
+                for c in 0..3 {
+                    pixel.0[c] += (old[c] - new[c]) * weight / 16.0;
+                }
+            }
+        }
+    }
+}
+
+/// Lines of randomly chosen 5x7 glyphs with ragged right edges and occasional spaces.
+fn text(width: u32, height: u32, rng: &mut StdRng) -> Rgb32FImage {
+    const MARGIN: u32 = 4;
+    let glyphs: Vec<[u8; 7]> = (0..26)
+        .map(|_| std::array::from_fn(|_| rng.gen::<u8>() & 0x1f))
+        .collect();
+
+    let mut image = Rgb32FImage::from_pixel(width, height, Rgb([1.0; 3]));
+    let mut y = MARGIN;
+    while y + 7 <= height.saturating_sub(MARGIN) {
+        let line_end = rng.gen_range(width / 2..=width.saturating_sub(MARGIN).max(width / 2));
+        let mut x = MARGIN;
+        while x + 5 <= line_end {
+            if !rng.gen_bool(0.15) {
+                let glyph = &glyphs[rng.gen_range(0..glyphs.len())];
+                for (row, bits) in glyph.iter().enumerate() {
+                    for column in 0..5 {
+                        if bits >> (4 - column) & 1 == 1 {
+                            image.put_pixel(x + column, y + row as u32, Rgb([0.0; 3]));
+                        }
+                    }
+                }
+            }
+            x += 6;
+        }
+        y += 10;
+    }
+    image
+}
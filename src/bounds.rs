@@ -0,0 +1,152 @@
+//! Estimates of how small each corpus file could get, to show how much headroom encoders have
+//! left rather than only how they compare with each other.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use rayon::prelude::*;
+
+use crate::{normalize, results::Measurement};
+
+/// General purpose compressors to use as a reference, in order of preference, with the arguments
+/// for their strongest settings. The first one installed is used.
+const REFERENCE_COMPRESSORS: [(&str, &[&str]); 2] = [
+    ("zstd", &["-19", "--long", "-q", "-c"]),
+    ("xz", &["-9e", "-c"]),
+];
+
+/// Lower bounds on the compressed size of one file's pixel data
+struct Bounds {
+    /// Size at the order-0 entropy of the bytes, in bytes
+    entropy: f64,
+    /// Size compressed with the reference compressor, if one is installed
+    reference: Option<u64>,
+}
+
+/// Size in bytes that `data` would take at its order-0 entropy, treating each byte independently.
+fn order0_entropy(data: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let total = data.len() as f64;
+    let bits: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| -(count as f64) * (count as f64 / total).log2())
+        .sum();
+    bits / 8.0
+}
+
+/// Returns the first reference compressor that is installed.
+fn reference_compressor() -> Option<(&'static str, &'static [&'static str])> {
+    REFERENCE_COMPRESSORS.into_iter().find(|(tool, _)| {
+        Command::new(tool)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    })
+}
+
+/// Compresses the file at `input` with the reference compressor and returns the compressed size.
+fn compressed_size(compressor: (&str, &[&str]), input: &Path) -> Option<u64> {
+    let (tool, args) = compressor;
+    let output = Command::new(tool).args(args).arg(input).output().ok()?;
+    output
+        .status
+        .success()
+        .then_some(output.stdout.len() as u64)
+}
+
+/// Prints each implementation's total output size relative to the order-0 entropy of the pixel
+/// data it was given and to the strongest general purpose compressor installed. The pixel data is
+/// the 8-bit RGB or RGBA the encoders are fed.
+pub fn print(results: &[Measurement]) {
+    let paths: BTreeSet<&Path> = results
+        .iter()
+        .flat_map(|m| m.samples.iter().map(|s| s.path.as_path()))
+        .collect();
+    let compressor = reference_compressor();
+    let directory = std::env::temp_dir().join(format!("corpus-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let mut bounds: HashMap<&Path, Bounds> = paths
+        .into_iter()
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .enumerate()
+        .filter_map(|(i, path)| {
+            let image = crate::archive::open_image(path).ok()?;
+            let image = normalize::to_rgb8_or_rgba8(&image);
+            let pixels = image.as_bytes();
+
+            let reference = compressor.and_then(|compressor| {
+                let input = directory.join(format!("pixels-{}.raw", i));
+                std::fs::write(&input, pixels).unwrap();
+                let size = compressed_size(compressor, &input);
+                std::fs::remove_file(&input).unwrap();
+                size
+            });
+            let bounds = Bounds {
+                entropy: order0_entropy(pixels),
+                reference,
+            };
+            Some((path, bounds))
+        })
+        .collect();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    // A single-colored image has no entropy to compare against, so it is left out of every sum
+    // rather than allowing an encoder's output for it to count as infinitely far from the bound.
+    let measured = bounds.len();
+    bounds.retain(|_, bounds| bounds.entropy > 0.0);
+    let flat = measured - bounds.len();
+
+    let width = crate::results::name_width(results);
+    println!();
+    match compressor {
+        Some((tool, args)) => println!(
+            "Output size relative to order-0 entropy and {} {}:",
+            tool, args[0]
+        ),
+        None => println!(
+            "Output size relative to order-0 entropy (install zstd or xz for a stronger bound):"
+        ),
+    }
+    if flat > 0 {
+        println!("({} files of a single color are left out)", flat);
+    }
+    for measurement in results {
+        let (mut compressed, mut entropy, mut reference) = (0, 0.0, Some(0));
+        for sample in &measurement.samples {
+            let Some(bounds) = bounds.get(sample.path.as_path()) else {
+                continue;
+            };
+            compressed += sample.compressed_bytes;
+            entropy += bounds.entropy;
+            reference = reference.zip(bounds.reference).map(|(a, b)| a + b);
+        }
+        if compressed == 0 {
+            continue;
+        }
+
+        let name = format!("{}:", measurement.name);
+        let versus_reference = match (compressor, reference) {
+            (Some((tool, _)), Some(reference)) => {
+                format!("  {:>6.2}x {}", compressed as f64 / reference as f64, tool)
+            }
+            _ => String::new(),
+        };
+        println!(
+            "{:<width$} {:>6.2}x entropy{}",
+            name,
+            compressed as f64 / entropy,
+            versus_reference
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Evicting the CPU caches before each timed region, to measure cold-start performance, and
+//! dropping files from the page cache for the modes that time reading them from disk.
+
+use std::{
+    hint::black_box,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// Size of the buffer written to evict the caches, which should exceed the last level cache.
+const EVICTION_BUFFER_SIZE: usize = 64 << 20;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static BUFFER: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Touches every cache line of a large buffer if cold-cache mode is enabled.
+pub fn evict() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut buffer = BUFFER.lock().unwrap();
+    buffer.resize(EVICTION_BUFFER_SIZE, 0);
+    for line in buffer.chunks_mut(64) {
+        line[0] = line[0].wrapping_add(1);
+    }
+    black_box(&mut *buffer);
+}
+
+/// Asks the kernel to drop `path` from the page cache, so that reading it next has to go to disk.
+#[cfg(target_os = "linux")]
+pub fn drop_page_cache(path: &Path) {
+    use std::os::fd::AsRawFd;
+
+    let (source, offset, len) = crate::archive::location(path);
+    let file = std::fs::File::open(source).unwrap();
+    // SAFETY: the file descriptor is valid until `file` is dropped.
+    let result = unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            libc::POSIX_FADV_DONTNEED,
+        )
+    };
+    assert_eq!(result, 0, "posix_fadvise failed on {}", path.display());
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_page_cache(_path: &Path) {
+    unreachable!("--drop-page-cache is rejected on platforms other than Linux");
+}
@@ -0,0 +1,163 @@
+//! Checking that image-rs and zune-png decode PNG files to the same pixels, and reducing files
+//! they disagree on to the smallest file that still shows the same disagreement.
+
+use std::{io::Cursor, path::Path};
+
+use image::Rgba32FImage;
+use zune_png::zune_core::options::DecoderOptions;
+
+use crate::normalize;
+
+/// Directory minimized copies of files the decoders disagree on are written to.
+const OUTPUT_DIRECTORY: &str = "disagreements";
+
+/// How two decoders disagreed on a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Disagreement {
+    /// Both decoded the file, to different pixels
+    Pixels,
+    /// Only image-rs failed to decode the file
+    ImageRsFailed,
+    /// Only zune-png failed to decode the file
+    ZunePngFailed,
+}
+
+/// Decodes `bytes` with zune-png, converted to a common pixel format for comparison.
+fn zune_png_decode(bytes: &[u8]) -> Option<Rgba32FImage> {
+    let mut decoder = zune_png::PngDecoder::new_with_options(
+        Cursor::new(bytes),
+        DecoderOptions::new_fast()
+            .set_max_width(usize::MAX)
+            .set_max_height(usize::MAX),
+    );
+    let pixels = decoder.decode().ok()?;
+    let (width, height) = decoder.dimensions()?;
+    let (width, height) = (width as u32, height as u32);
+    let channels = decoder.colorspace()?.num_components();
+
+    let image = normalize::from_zune(pixels, width, height, channels)?;
+    Some(image.to_rgba32f())
+}
+
+/// Decodes `bytes` with both decoders and reports how they disagree, if they do. Files that
+/// neither can decode count as agreement.
+pub fn compare(bytes: &[u8]) -> Option<Disagreement> {
+    let image_rs = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+        .ok()
+        .map(|image| image.to_rgba32f());
+    match (image_rs, zune_png_decode(bytes)) {
+        (Some(a), Some(b)) if a.dimensions() != b.dimensions() || a.as_raw() != b.as_raw() => {
+            Some(Disagreement::Pixels)
+        }
+        (None, Some(_)) => Some(Disagreement::ImageRsFailed),
+        (Some(_), None) => Some(Disagreement::ZunePngFailed),
+        _ => None,
+    }
+}
+
+/// Splits a PNG file into its signature and the raw bytes of each chunk, or returns `None` if it
+/// isn't made of well-formed chunks.
+fn split_chunks(bytes: &[u8]) -> Option<(&[u8], Vec<Vec<u8>>)> {
+    let (signature, mut rest) = bytes.split_at_checked(8)?;
+    let mut chunks = Vec::new();
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let (chunk, remainder) = rest.split_at_checked(len.checked_add(12)?)?;
+        chunks.push(chunk.to_vec());
+        rest = remainder;
+    }
+    Some((signature, chunks))
+}
+
+/// Rebuilds `original` with new data. The CRC is only kept correct if the original's was, since
+/// the disagreement may be about how the decoders handle CRC errors.
+fn rebuild_chunk(original: &[u8], data: &[u8]) -> Vec<u8> {
+    let (body, crc) = original.split_at(original.len() - 4);
+    let crc_correct = crc32fast::hash(&body[4..]).to_be_bytes() == crc;
+
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(&original[4..8]);
+    chunk.extend_from_slice(data);
+    let crc = crc32fast::hash(&chunk[4..]);
+    chunk.extend_from_slice(&if crc_correct { crc } else { !crc }.to_be_bytes());
+    chunk
+}
+
+/// Candidate reductions of `chunks`: dropping any chunk but the header, halving the image height
+/// so fewer scanlines are decoded, and truncating the data of any chunk to half its length.
+fn reductions(chunks: &[Vec<u8>]) -> Vec<Vec<Vec<u8>>> {
+    let mut candidates = Vec::new();
+    for i in 1..chunks.len() {
+        let mut reduced = chunks.to_vec();
+        reduced.remove(i);
+        candidates.push(reduced);
+    }
+
+    if let Some(header) = chunks
+        .first()
+        .filter(|c| &c[4..8] == b"IHDR" && c.len() >= 25)
+    {
+        let height = u32::from_be_bytes(header[12..16].try_into().unwrap());
+        if height > 1 {
+            let mut data = header[8..header.len() - 4].to_vec();
+            data[4..8].copy_from_slice(&(height / 2).to_be_bytes());
+            let mut reduced = chunks.to_vec();
+            reduced[0] = rebuild_chunk(header, &data);
+            candidates.push(reduced);
+        }
+    }
+
+    for (i, chunk) in chunks.iter().enumerate().skip(1) {
+        let data = &chunk[8..chunk.len() - 4];
+        if !data.is_empty() {
+            let mut reduced = chunks.to_vec();
+            reduced[i] = rebuild_chunk(chunk, &data[..data.len() / 2]);
+            candidates.push(reduced);
+        }
+    }
+    candidates
+}
+
+/// Greedily applies reductions to `bytes` for as long as the decoders still disagree on the result
+/// in the same way.
+pub fn minimize(bytes: &[u8], disagreement: Disagreement) -> Vec<u8> {
+    let Some((signature, mut chunks)) = split_chunks(bytes) else {
+        return bytes.to_vec();
+    };
+    let join = |chunks: &[Vec<u8>]| [signature, &chunks.concat()].concat();
+
+    'reduce: loop {
+        for candidate in reductions(&chunks) {
+            if compare(&join(&candidate)) == Some(disagreement) {
+                chunks = candidate;
+                continue 'reduce;
+            }
+        }
+        break;
+    }
+
+    let minimized = join(&chunks);
+    if minimized.len() < bytes.len() {
+        minimized
+    } else {
+        bytes.to_vec()
+    }
+}
+
+/// Minimizes a file the decoders disagree on and saves it to the output directory, named by its
+/// hash so that repeated runs don't pile up copies.
+pub fn save(path: &Path, bytes: &[u8], disagreement: Disagreement) {
+    let minimized = minimize(bytes, disagreement);
+    std::fs::create_dir_all(OUTPUT_DIRECTORY).unwrap();
+    let output =
+        Path::new(OUTPUT_DIRECTORY).join(format!("{:08x}.png", crc32fast::hash(&minimized)));
+    std::fs::write(&output, &minimized).unwrap();
+    println!(
+        "Decoders disagree on {} ({:?}); minimized from {} to {} bytes in {}",
+        path.display(),
+        disagreement,
+        bytes.len(),
+        minimized.len(),
+        output.display()
+    );
+}
@@ -0,0 +1,52 @@
+//! Streaming each sample as a line of JSON as soon as it is taken, so that dashboards and
+//! notebooks can follow a run live instead of waiting for the summary at the end.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Mutex, OnceLock},
+};
+
+static SINK: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+/// Starts writing events to the file at `path`.
+pub fn open(path: &Path) {
+    let sink: Box<dyn Write + Send> = Box::new(BufWriter::new(File::create(path).unwrap()));
+    assert!(SINK.set(Mutex::new(sink)).is_ok(), "events already opened");
+}
+
+/// Quotes `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            c if c.is_control() => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted + "\""
+}
+
+/// Writes a sample taken by `implementation` on `path` if events are being written. Each line is
+/// flushed straight away so that readers see it immediately.
+pub fn sample(implementation: &str, path: &Path, nanos: u128, pixels: u64, bytes: u64) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let mut sink = sink.lock().unwrap();
+    writeln!(
+        sink,
+        "{{\"impl\":{},\"file\":{},\"nanos\":{},\"pixels\":{},\"bytes\":{}}}",
+        json_string(implementation),
+        json_string(&path.to_string_lossy()),
+        nanos,
+        pixels,
+        bytes
+    )
+    .unwrap();
+    sink.flush().unwrap();
+}
@@ -0,0 +1,106 @@
+//! Reading the orientation tag from EXIF data, as found in PNG eXIf chunks.
+
+/// EXIF tag holding the orientation of the image
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// TIFF field type of a 16-bit unsigned integer
+const SHORT: u16 = 3;
+
+/// Returns the orientation recorded in the first image directory of `exif`, from 1 (as stored) to
+/// 8, or `None` if there isn't one or the data is malformed. `tiff` is a TIFF header followed by
+/// its directories, as an eXIf chunk holds.
+pub fn orientation(tiff: &[u8]) -> Option<u8> {
+    let big_endian = match tiff.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = tiff.get(offset..offset.checked_add(2)?)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = tiff.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let directory = read_u32(4)? as usize;
+    for i in 0..usize::from(read_u16(directory)?) {
+        // Each entry is a tag, a type, a count and then the value itself if it fits in 4 bytes.
+        let entry = directory + 2 + 12 * i;
+        if read_u16(entry)? == ORIENTATION_TAG {
+            if read_u16(entry + 2)? != SHORT {
+                return None;
+            }
+            let orientation = read_u16(entry + 8)?;
+            return (1..=8).contains(&orientation).then_some(orientation as u8);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little-endian TIFF header and a directory holding `entries` of (tag, type, value).
+    fn little_endian(entries: &[(u16, u16, u16)]) -> Vec<u8> {
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend((entries.len() as u16).to_le_bytes());
+        for &(tag, kind, value) in entries {
+            tiff.extend(tag.to_le_bytes());
+            tiff.extend(kind.to_le_bytes());
+            tiff.extend(1u32.to_le_bytes());
+            tiff.extend(value.to_le_bytes());
+            tiff.extend([0, 0]);
+        }
+        tiff.extend(0u32.to_le_bytes());
+        tiff
+    }
+
+    #[test]
+    fn orientation_is_found_among_other_tags() {
+        let tiff = little_endian(&[(0x0100, SHORT, 640), (ORIENTATION_TAG, SHORT, 6)]);
+        assert_eq!(orientation(&tiff), Some(6));
+    }
+
+    #[test]
+    fn big_endian_orientation() {
+        let mut tiff = b"MM\0*".to_vec();
+        tiff.extend(8u32.to_be_bytes());
+        tiff.extend(1u16.to_be_bytes());
+        tiff.extend(ORIENTATION_TAG.to_be_bytes());
+        tiff.extend(SHORT.to_be_bytes());
+        tiff.extend(1u32.to_be_bytes());
+        tiff.extend(8u16.to_be_bytes());
+        tiff.extend([0, 0]);
+        assert_eq!(orientation(&tiff), Some(8));
+    }
+
+    #[test]
+    fn missing_or_invalid_orientations_are_none() {
+        assert_eq!(orientation(&little_endian(&[(0x0100, SHORT, 640)])), None);
+        assert_eq!(
+            orientation(&little_endian(&[(ORIENTATION_TAG, SHORT, 9)])),
+            None
+        );
+        assert_eq!(
+            orientation(&little_endian(&[(ORIENTATION_TAG, 4, 6)])),
+            None
+        );
+        // Truncated in the middle of the orientation entry.
+        let tiff = little_endian(&[(ORIENTATION_TAG, SHORT, 6)]);
+        assert_eq!(orientation(&tiff[..16]), None);
+        assert_eq!(orientation(b"not exif"), None);
+        assert_eq!(orientation(&[]), None);
+    }
+}
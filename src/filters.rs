@@ -0,0 +1,136 @@
+//! Counting which scanline filter each PNG encoder chose for every row of its output, and how
+//! those choices relate to the compression ratio it achieved.
+
+use std::io::Cursor;
+
+use rayon::prelude::*;
+
+use crate::normalize;
+
+/// Names of the five PNG filter types, indexed by filter type
+const FILTER_NAMES: [&str; 5] = ["None", "Sub", "Up", "Average", "Paeth"];
+
+/// The eight bytes every PNG file starts with
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Returns how many rows of a non-interlaced PNG file use each filter type, or `None` if the file
+/// is interlaced or can't be parsed.
+fn filter_counts(png: &[u8]) -> Option<[u64; 5]> {
+    let mut rest = png.strip_prefix(PNG_SIGNATURE)?;
+    let mut header = None;
+    let mut compressed = Vec::new();
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let data = rest.get(8..8 + len)?;
+        match &rest[4..8] {
+            b"IHDR" => header = Some(data.to_vec()),
+            b"IDAT" => compressed.extend_from_slice(data),
+            _ => {}
+        }
+        rest = rest.get(len + 12..)?;
+    }
+
+    let header = header.filter(|header| header.len() == 13 && header[12] == 0)?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let bit_depth = header[8] as usize;
+    let channels = match header[9] {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return None,
+    };
+    let row_bytes = 1 + (width * channels * bit_depth).div_ceil(8);
+
+    let filtered = fdeflate::decompress_to_vec(&compressed).ok()?;
+    let mut counts = [0; 5];
+    for row in filtered.chunks_exact(row_bytes) {
+        *counts.get_mut(row[0] as usize)? += 1;
+    }
+    Some(counts)
+}
+
+/// Filter choices and compression achieved by one encoder on one file
+struct FileStats {
+    counts: [u64; 5],
+    raw_bytes: u64,
+    compressed_bytes: u64,
+}
+
+/// Encodes every file with each encoder that produces PNG, and prints the share of rows each
+/// encoder applied every filter type to, along with its compression ratio on the files where each
+/// filter type was the one it used most.
+pub fn print(corpus: &[std::path::PathBuf]) {
+    let files: Vec<Vec<Option<FileStats>>> = corpus
+        .par_iter()
+        .filter_map(|path| {
+            let image = crate::archive::open_image(path).ok()?;
+            let image = normalize::to_rgb8_or_rgba8(&image);
+            let stats = crate::ENCODERS
+                .iter()
+                .map(|(_, write)| {
+                    let mut buffer = Cursor::new(Vec::new());
+                    write(&mut buffer, &image);
+                    let output = buffer.into_inner();
+                    Some(FileStats {
+                        counts: filter_counts(&output)?,
+                        raw_bytes: image.as_bytes().len() as u64,
+                        compressed_bytes: output.len() as u64,
+                    })
+                })
+                .collect();
+            Some(stats)
+        })
+        .collect();
+
+    let width = crate::ENCODERS
+        .iter()
+        .map(|(name, _)| name.len() + 1)
+        .max()
+        .unwrap_or(0);
+    println!();
+    println!("Share of rows using each filter, and ratio on files where it was used most:");
+    println!(
+        "{:<width$} {}",
+        "",
+        FILTER_NAMES
+            .map(|name| format!("{:>8} {:>9}", name, ""))
+            .join("")
+            .trim_end()
+    );
+    for (i, (name, _)) in crate::ENCODERS.iter().enumerate() {
+        let stats: Vec<&FileStats> = files.iter().filter_map(|file| file[i].as_ref()).collect();
+        if stats.is_empty() {
+            // Not a PNG encoder.
+            continue;
+        }
+
+        let mut rows = [0; 5];
+        let mut by_dominant = [(0, 0, 0); 5];
+        for file in &stats {
+            let dominant = (0..5).max_by_key(|&filter| file.counts[filter]).unwrap();
+            let (raw, compressed, count) = &mut by_dominant[dominant];
+            *raw += file.raw_bytes;
+            *compressed += file.compressed_bytes;
+            *count += 1;
+            for (rows, count) in rows.iter_mut().zip(file.counts) {
+                *rows += count;
+            }
+        }
+
+        let total_rows = rows.iter().sum::<u64>().max(1) as f64;
+        let cells = (0..5).map(|filter| {
+            let share = rows[filter] as f64 / total_rows * 100.0;
+            let ratio = match by_dominant[filter] {
+                (_, _, 0) => String::new(),
+                (raw, compressed, _) => format!("{:.2}:1", raw as f64 / compressed as f64),
+            };
+            format!("{:>7.1}% {:>9}", share, ratio)
+        });
+        println!(
+            "{:<width$} {}",
+            format!("{}:", name),
+            cells.collect::<String>().trim_end()
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! The corpus lockfile, which records where each corpus came from and a hash of every file in it,
+//! so that results can be tied to an exact corpus state.
+//!
+//! Each line holds tab separated fields, starting with the name of the corpus and the kind of
+//! entry (shown here separated by spaces):
+//!
+//! ```text
+//! qoi-bench source https://qoiformat.org/benchmark/qoi_benchmark_suite.tar
+//! qoi-bench archive <crc32>
+//! qoi-bench tool    corpus-bench 0.0.0 (<git commit>)
+//! qoi-bench file    <crc32> <size> <path> <modification time>
+//! ```
+//!
+//! Hashing a large corpus on every run takes a while, so a file is only hashed again when its size
+//! or modification time differ from what the lockfile records.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::Corpus;
+
+const LOCK_PATH: &str = "corpus.lock";
+
+/// Maximum number of mismatched files to list.
+const MAX_MISMATCHES_LISTED: usize = 10;
+
+/// A corpus file as the lockfile records it
+struct LockedFile {
+    crc: u32,
+    size: u64,
+    /// Nanoseconds since the Unix epoch, if known
+    modified: Option<u128>,
+}
+
+/// The version of corpus-bench that wrote a lockfile.
+fn tool_version() -> String {
+    format!(
+        "corpus-bench {} ({})",
+        env!("CARGO_PKG_VERSION"),
+        env!("CORPUS_BENCH_GIT_HASH")
+    )
+}
+
+/// When the file holding a corpus file was last modified, in nanoseconds since the Unix epoch.
+fn modified(path: &Path) -> Option<u128> {
+    let (source, _, _) = crate::archive::location(path);
+    let modified = std::fs::metadata(source).ok()?.modified().ok()?;
+    Some(
+        modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_nanos(),
+    )
+}
+
+/// Describes every file in `files`, hashing only those whose size or modification time differ
+/// from `locked`.
+fn current_files(
+    files: &[PathBuf],
+    locked: &HashMap<String, LockedFile>,
+) -> Vec<(String, LockedFile)> {
+    files
+        .iter()
+        .map(|path| {
+            let name = path.display().to_string();
+            let size = crate::archive::len(path).unwrap();
+            let modified = modified(path);
+            let crc = match locked.get(&name) {
+                Some(entry)
+                    if entry.size == size && modified.is_some() && entry.modified == modified =>
+                {
+                    entry.crc
+                }
+                _ => crc32fast::hash(&crate::archive::read(path).unwrap()),
+            };
+            let file = LockedFile {
+                crc,
+                size,
+                modified,
+            };
+            (name, file)
+        })
+        .collect()
+}
+
+/// Reads the lockfile as pairs of corpus name and the rest of the entry.
+fn read_lock() -> Vec<(String, String)> {
+    let Ok(contents) = std::fs::read_to_string(LOCK_PATH) else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(corpus, entry)| (corpus.to_owned(), entry.to_owned()))
+        .collect()
+}
+
+/// The files the lockfile records for the corpus called `name`, keyed by path. Entries written
+/// before modification times were recorded have none.
+fn locked_files(lock: &[(String, String)], name: &str) -> HashMap<String, LockedFile> {
+    lock.iter()
+        .filter(|(corpus, _)| corpus == name)
+        .filter_map(|(_, entry)| {
+            let fields: Vec<&str> = entry.split('\t').collect();
+            let (crc, size, path, modified) = match fields[..] {
+                ["file", crc, size, path] => (crc, size, path, None),
+                ["file", crc, size, path, modified] => (crc, size, path, modified.parse().ok()),
+                _ => return None,
+            };
+            let file = LockedFile {
+                crc: u32::from_str_radix(crc, 16).ok()?,
+                size: size.parse().ok()?,
+                modified,
+            };
+            Some((path.to_owned(), file))
+        })
+        .collect()
+}
+
+/// Replaces the entries for `corpus` in the lockfile with its current contents.
+pub fn update(corpus: &Corpus) {
+    let name = corpus.name();
+    let (source, archive) = corpus.source();
+
+    let mut entries = vec![format!("source\t{}", source)];
+    if let Some(archive) = archive.and_then(|archive| std::fs::read(archive).ok()) {
+        entries.push(format!("archive\t{:08x}", crc32fast::hash(&archive)));
+    }
+    entries.push(format!("tool\t{}", tool_version()));
+    for (path, file) in current_files(&corpus.files(), &HashMap::new()) {
+        entries.push(format!(
+            "file\t{:08x}\t{}\t{}\t{}",
+            file.crc,
+            file.size,
+            path,
+            file.modified
+                .map_or(String::new(), |modified| modified.to_string())
+        ));
+    }
+
+    let mut contents = String::new();
+    for (other, entry) in read_lock() {
+        if other != name {
+            contents += &format!("{}\t{}\n", other, entry);
+        }
+    }
+    for entry in &entries {
+        contents += &format!("{}\t{}\n", name, entry);
+    }
+    std::fs::write(LOCK_PATH, contents).unwrap();
+    info!("Updated {} for corpus {}", LOCK_PATH, name);
+}
+
+/// Prints and returns a fingerprint of the current contents of `corpus`, and checks them against
+/// what the lockfile records. If any file differs, they are listed and the run is refused unless
+/// `allow_mismatch` is set.
+pub fn check(corpus: &Corpus, allow_mismatch: bool) -> String {
+    let name = corpus.name();
+    let lock = read_lock();
+    let locked = locked_files(&lock, &name);
+    let current = current_files(&corpus.files(), &locked);
+    // Modification times are left out, so that the same files give the same fingerprint wherever
+    // they were extracted.
+    let fingerprint_entries: Vec<String> = current
+        .iter()
+        .map(|(path, file)| format!("file\t{:08x}\t{}\t{}", file.crc, file.size, path))
+        .collect();
+    let fingerprint = format!(
+        "{:08x}",
+        crc32fast::hash(fingerprint_entries.join("\n").as_bytes())
+    );
+    info!("Corpus fingerprint: {}", fingerprint);
+
+    if locked.is_empty() {
+        info!(
+            "No entry for corpus {} in {}, run with --update-lock to add one",
+            name, LOCK_PATH
+        );
+        return fingerprint;
+    }
+    if let Some((_, tool)) = lock
+        .iter()
+        .find(|(corpus, entry)| *corpus == name && entry.starts_with("tool\t"))
+    {
+        let tool = &tool["tool\t".len()..];
+        if tool != tool_version() {
+            info!("{} was written by {}", LOCK_PATH, tool);
+        }
+    }
+
+    let mut mismatches: Vec<&str> = current
+        .iter()
+        .filter(|(path, file)| {
+            locked
+                .get(path)
+                .is_none_or(|entry| entry.crc != file.crc || entry.size != file.size)
+        })
+        .map(|(path, _)| path.as_str())
+        .collect();
+    let current_paths: HashSet<&str> = current.iter().map(|(path, _)| path.as_str()).collect();
+    mismatches.extend(
+        locked
+            .keys()
+            .map(String::as_str)
+            .filter(|path| !current_paths.contains(path)),
+    );
+    mismatches.sort();
+
+    if !mismatches.is_empty() {
+        eprintln!(
+            "{} corpus files are changed, missing or not in {}:",
+            mismatches.len(),
+            LOCK_PATH
+        );
+        for path in mismatches.iter().take(MAX_MISMATCHES_LISTED) {
+            eprintln!("  {}", path);
+        }
+        if !allow_mismatch {
+            eprintln!(
+                "Run with --update-lock to record the current files, or --allow-lock-mismatch to \
+                 benchmark them anyway"
+            );
+            std::process::exit(1);
+        }
+    }
+    fingerprint
+}
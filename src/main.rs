@@ -1,354 +1,3364 @@
 use std::{
+    collections::HashMap,
+    fmt,
     hint::black_box,
-    io::{Cursor, Write},
-    path::PathBuf,
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{Once, OnceLock},
+    time::{Duration, Instant},
 };
 
-use clap::{arg, command, Parser, ValueEnum};
-use image::{DynamicImage, ImageFormat};
+use clap::{Parser, ValueEnum};
+use image::{
+    imageops::FilterType, ColorType, DynamicImage, ImageDecoder, ImageEncoder, ImageFormat,
+};
 use rand::prelude::*;
+use rayon::prelude::*;
+use results::{Measurement, Outcome, OutputBuffer, Profile, Sample, SortOrder, Timer, Unit};
 use walkdir::WalkDir;
 
-#[derive(Parser, Debug)]
+/// Prints a status line unless running with `--quiet`.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if crate::verbosity() >= crate::Verbosity::Normal {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "count-allocations")]
+mod alloc;
+mod archive;
+mod bounds;
+mod cache;
+mod differential;
+mod events;
+mod exif;
+mod filters;
+mod lock;
+mod normalize;
+mod pins;
+mod results;
+mod results_file;
+mod rss;
+mod size_curve;
+mod stats;
+#[cfg(test)]
+mod testing;
+
+#[derive(Parser, Clone, Debug)]
 #[command(
     version,
-    about = "Measure the performance of encoding or decoding a given corpus"
+    about = "Measure the performance of encoding or decoding a given corpus",
+    group(clap::ArgGroup::new("comparison").multiple(true))
 )]
 struct Args {
     #[arg(value_enum, index = 1)]
     mode: Mode,
 
-    #[arg(value_enum, index = 2)]
-    corpus: Corpus,
+    #[arg(value_enum, index = 2)]
+    corpus: Corpus,
+
+    /// Seed used to shuffle the corpus, chosen randomly if not given
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Process the corpus in sorted path order instead of shuffling it
+    #[arg(long, conflicts_with = "seed")]
+    no_shuffle: bool,
+
+    /// Only benchmark a stratified sample of roughly a tenth of the corpus
+    #[arg(long)]
+    fast: bool,
+
+    /// Only benchmark the I-th of N disjoint parts of the corpus, so that a run can be split
+    /// across machines
+    #[arg(long, value_name = "I/N")]
+    shard: Option<Shard>,
+
+    /// Stop starting new files once each implementation, or group of implementations measured
+    /// together, has spent this many seconds in timed code
+    #[arg(long)]
+    time_budget: Option<f64>,
+
+    /// Check that every implementation in every mode runs on a few representative files, and
+    /// that every encoder's output decodes, without reporting any timings
+    #[arg(long, conflicts_with_all = ["cold", "repeat", "merge", "ratio_only"])]
+    dry_run: bool,
+
+    /// Only compute the compression ratio of each encoder, using every core and without timing
+    #[arg(long, conflicts_with_all = ["time_budget", "cold", "repeat", "merge"])]
+    ratio_only: bool,
+
+    /// Only report which scanline filters each PNG encoder chooses and the compression ratio it
+    /// achieves with them, using every core and without timing
+    #[arg(long, conflicts_with_all = ["time_budget", "cold", "repeat", "merge", "ratio_only"])]
+    filter_stats: bool,
+
+    /// Compute every encoder's output size for every file without timing, and pin them in this
+    /// file if it doesn't exist yet. Otherwise, list the files whose output size changed since
+    /// and exit with status 1 if any did
+    #[arg(long, value_name = "PATH", conflicts_with_all = [
+        "time_budget", "cold", "repeat", "merge", "ratio_only", "filter_stats",
+    ])]
+    pin_sizes: Option<PathBuf>,
+
+    /// Re-pin output sizes in the --pin-sizes file after listing changes
+    #[arg(long, requires = "pin_sizes")]
+    update_pins: bool,
+
+    /// Time these two encoders back-to-back in random order on every file, several times each,
+    /// and report their per-file time ratios, which cancels out drift in the machine's speed
+    #[arg(long, num_args = 2, value_names = ["A", "B"], conflicts_with_all = [
+        "cold", "repeat", "merge", "ratio_only", "filter_stats", "pin_sizes",
+    ])]
+    paired: Vec<String>,
+
+    /// Number of times each file is encoded by both encoders with --paired
+    #[arg(
+        long,
+        default_value_t = 5,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "paired"
+    )]
+    paired_rounds: u32,
+
+    /// Read the corpus from this tar archive instead of the directory it is normally extracted to
+    #[arg(long, value_name = "PATH")]
+    archive: Option<PathBuf>,
+
+    /// Also compare each encoder's output size with the order-0 entropy of its input and with the
+    /// output of a strong general purpose compressor
+    #[arg(long)]
+    compression_bound: bool,
+
+    /// In decode-io mode, drop each file from the page cache before reading it (Linux only)
+    #[arg(long)]
+    drop_page_cache: bool,
+
+    /// Also run with the CPU caches evicted before every timed sample, and compare the latencies
+    #[arg(long)]
+    cold: bool,
+
+    /// Unit to report throughput in, instead of the default for the mode
+    #[arg(long, value_enum)]
+    unit: Option<Unit>,
+
+    /// Order to print implementations in, instead of the order they are measured in
+    #[arg(long, value_enum)]
+    sort: Option<SortOrder>,
+
+    /// Also report geomean throughput with corpus categories weighted for this target workload
+    #[arg(long, value_enum)]
+    profile: Option<Profile>,
+
+    /// Implementation to test every other implementation against for significant differences
+    #[arg(long)]
+    reference: Option<String>,
+
+    /// Number of times to run the whole benchmark, reporting median per-file times and how
+    /// stable they were between runs
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    repeat: u32,
+
+    /// Write the per-file results to this path, for combining with other shards later
+    #[arg(long, value_name = "PATH")]
+    results: Option<PathBuf>,
+
+    /// Results saved by --results from an earlier run, to list the files that got slowest since
+    #[arg(long, value_name = "PATH", group = "comparison")]
+    baseline: Option<PathBuf>,
+
+    /// Number of files with the largest slowdowns versus the baseline to list per implementation
+    #[arg(long, default_value_t = 5, value_name = "N")]
+    regressions: usize,
+
+    /// Percentage change in overall time versus the baseline below which speed counts as unchanged
+    #[arg(
+        long,
+        default_value_t = 2.0,
+        value_name = "PERCENT",
+        requires = "baseline"
+    )]
+    time_threshold: f64,
+
+    /// Highest p-value at which a change in time versus the baseline counts as significant
+    #[arg(long, default_value_t = 0.05, value_name = "P", requires = "baseline")]
+    alpha: f64,
+
+    /// Percentage change in overall output size versus the baseline below which compression
+    /// counts as unchanged
+    #[arg(
+        long,
+        default_value_t = 0.5,
+        value_name = "PERCENT",
+        requires = "baseline"
+    )]
+    size_threshold: f64,
+
+    /// Results saved by --results from a run on another architecture, to compare how
+    /// implementations rank on each
+    #[arg(long, value_name = "PATH", group = "comparison")]
+    compare_arch: Option<PathBuf>,
+
+    /// Instead of running the benchmark, report on the combined results saved by --results
+    #[arg(long, value_name = "PATH", num_args = 1.., conflicts_with_all = [
+        "seed", "no_shuffle", "fast", "shard", "time_budget", "cold", "repeat", "update_lock",
+    ])]
+    merge: Vec<PathBuf>,
+
+    /// Compare against a --baseline or --compare-arch results file even if it was measured on a
+    /// different state of the corpus
+    #[arg(long, requires = "comparison")]
+    allow_corpus_drift: bool,
+
+    /// Merge results even if they were measured on different machines, builds or corpora
+    #[arg(long, requires = "merge")]
+    force_merge: bool,
+
+    /// Write each sample as a line of JSON to this path as soon as it is taken
+    #[arg(long, value_name = "PATH", conflicts_with = "merge")]
+    events: Option<PathBuf>,
+
+    /// Write a CSV histogram of per-file latencies to this path
+    #[arg(long, value_name = "PATH")]
+    latency_histogram: Option<PathBuf>,
+
+    /// Fit how each implementation's time depends on image size, print where one overtakes
+    /// another, and write the fitted throughput curves to this path as CSV
+    #[arg(long, value_name = "PATH")]
+    size_curve: Option<PathBuf>,
+
+    /// Write a CSV histogram of per-file compression ratios to this path, when encoding
+    #[arg(long, value_name = "PATH")]
+    ratio_histogram: Option<PathBuf>,
+
+    /// Record the current contents of the corpus in corpus.lock
+    #[arg(long)]
+    update_lock: bool,
+
+    /// Benchmark the corpus even if its files differ from what corpus.lock records
+    #[arg(long, conflicts_with = "update_lock")]
+    allow_lock_mismatch: bool,
+
+    /// Name identifying this run in its output
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Free-form note describing this run, which may be given more than once
+    #[arg(long = "note")]
+    notes: Vec<String>,
+
+    /// Only print the final results table
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print the time taken by every implementation on every file
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Run even if corpus-bench was compiled without optimizations
+    #[arg(long)]
+    allow_unoptimized: bool,
+}
+
+/// How much to print besides the results
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+fn verbosity() -> Verbosity {
+    *VERBOSITY.get().unwrap_or(&Verbosity::Normal)
+}
+
+/// The mode to run the benchmark in
+#[derive(ValueEnum, Clone, Debug)]
+enum Mode {
+    /// Measure the performance of encoding
+    Encode,
+    /// Measure the performance of encoding with encoder state created per image or reused
+    EncodeReuse,
+    /// Measure encoding to a sink that only counts bytes, compared with building a Vec
+    EncodeSink,
+    /// Measure encoding PNGs with an ICC profile, EXIF and text chunks, compared with bare images
+    EncodeMetadata,
+    /// Measure the cost of encoding from row-padded, BGR and planar pixel buffers rather than
+    /// tightly packed RGB
+    EncodeLayout,
+    /// Measure the performance of decoding
+    Decode,
+    /// Measure decoding with image-rs and zune-png while checking that they agree, saving
+    /// minimized copies of files they disagree on
+    Differential,
+    /// Measure the performance of reading files from disk and decoding them
+    DecodeIo,
+    /// Measure how decoders handle and how long they take to reject truncated files
+    Truncated,
+    /// Measure the overhead of decoding from a non-seekable stream rather than a slice
+    DecodeStream,
+    /// Measure the performance of decoding and re-encoding to a different format
+    Transcode,
+    /// Measure the time taken and size reduction achieved by external PNG optimizers
+    Optimize,
+    /// Measure sustained encoding and decoding of frame sequences with reused buffers
+    Frames,
+    /// Measure the performance of reading only image headers
+    Probe,
+    /// Measure the performance of reading PNG metadata chunks with the png crate, without
+    /// decoding pixels
+    Metadata,
+    /// Measure the performance of decoding and downscaling to a thumbnail
+    Thumbnail,
+    /// Measure the performance of downscaling decoded images with image-rs's different filters
+    Resize,
+    /// Measure the overhead of decoding with resource limits enabled
+    Limits,
+    /// Measure the performance of converting images with embedded ICC profiles to sRGB
+    Icc,
+    /// Measure the performance of decoding to linear-light f32 pixels
+    Linear,
+    /// Measure the performance of decoding 32-bit float OpenEXR and Radiance HDR images
+    DecodeHdr,
+    /// Measure encoding and decoding the uncompressed farbfeld and PNM formats, as an upper bound
+    /// on throughput
+    Uncompressed,
+    /// Measure the performance of decoding and applying each file's EXIF orientation
+    Orientation,
+    /// Measure the overhead the harness adds to every sample by timing implementations that do
+    /// nothing
+    Overhead,
+}
+
+/// One of `count` disjoint parts of the corpus, numbered from 1
+#[derive(Clone, Copy, Debug)]
+struct Shard {
+    index: usize,
+    count: usize,
+}
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected I/N with 1 <= I <= N, got {:?}", s);
+        let (index, count) = s.split_once('/').ok_or_else(error)?;
+        let (index, count) = (
+            index.parse().map_err(|_| error())?,
+            count.parse().map_err(|_| error())?,
+        );
+        if index == 0 || index > count {
+            return Err(error());
+        }
+        Ok(Self { index, count })
+    }
+}
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// The corpus to choose from
+#[derive(ValueEnum, Clone, Debug)]
+enum Corpus {
+    /// The QOI Benchmark corpus
+    QoiBench,
+    /// Images produced by the generate-synthetic binary
+    Synthetic,
+    /// Frame sequences produced by the generate-synthetic binary with --frames
+    Frames,
+    /// High dynamic range images produced by the generate-synthetic binary with --hdr
+    Hdr,
+}
+impl Corpus {
+    /// The name the corpus is selected by on the command line.
+    fn name(&self) -> String {
+        self.to_possible_value().unwrap().get_name().to_owned()
+    }
+
+    fn directory(&self) -> &'static str {
+        match self {
+            Corpus::QoiBench => "corpus/qoi_benchmark_suite",
+            Corpus::Synthetic => "corpus/synthetic",
+            Corpus::Frames => "corpus/frames",
+            Corpus::Hdr => "corpus/hdr",
+        }
+    }
+
+    /// Where the corpus comes from, and the archive it was extracted from if that is kept around.
+    fn source(&self) -> (&'static str, Option<&'static str>) {
+        match self {
+            Corpus::QoiBench => (
+                "https://qoiformat.org/benchmark/qoi_benchmark_suite.tar",
+                Some("corpus/qoi_benchmark_suite.tar"),
+            ),
+            Corpus::Synthetic => ("generate-synthetic", None),
+            Corpus::Frames => ("generate-synthetic --frames", None),
+            Corpus::Hdr => ("generate-synthetic --hdr", None),
+        }
+    }
+
+    /// Lists the files in the corpus in sorted order.
+    fn files(&self) -> Vec<PathBuf> {
+        if let Some(paths) = archive::files(Path::new(self.directory())) {
+            return paths;
+        }
+        let mut paths = Vec::new();
+        for entry in WalkDir::new(self.directory()) {
+            let entry = entry.unwrap();
+            if entry.file_type().is_file() {
+                paths.push(entry.path().to_owned());
+            }
+        }
+        paths.sort();
+        paths
+    }
+
+    /// Lists the files in the corpus, shuffled with the given seed or sorted if it is `None`. If
+    /// `fast` is set, only a stratified sample of the files is returned. A shard takes every N-th
+    /// file in sorted order, so shards are disjoint even when each is shuffled with its own seed.
+    fn get_corpus(&self, seed: Option<u64>, fast: bool, shard: Option<Shard>) -> Vec<PathBuf> {
+        let mut paths = self.files();
+        if fast {
+            let total = paths.len();
+            paths = match self {
+                Corpus::Frames => sample_sequences(paths),
+                _ => stratified_sample(paths),
+            };
+            info!("Sampled {} of {} corpus files", paths.len(), total);
+        }
+        paths.sort();
+        if let Some(shard) = shard {
+            let total = paths.len();
+            paths = paths
+                .into_iter()
+                .skip(shard.index - 1)
+                .step_by(shard.count)
+                .collect();
+            info!("Shard {}: {} of {} corpus files", shard, paths.len(), total);
+        }
+        if let Some(seed) = seed {
+            paths.shuffle(&mut StdRng::seed_from_u64(seed));
+        }
+        paths
+    }
+}
+
+/// `--fast` keeps roughly one in this many files from each stratum of the corpus.
+const FAST_SAMPLE_RATE: usize = 10;
+
+/// Selects roughly one in `FAST_SAMPLE_RATE` files from every combination of file size (rounded
+/// down to a power of two) and color type, spread evenly by size within each combination, so that
+/// the subset has the same composition as the full corpus. Files of the same size are ordered by
+/// a hash of their contents, so that the same files are chosen wherever the corpus is stored.
+/// Only file sizes and image headers are read, except to hash files whose sizes tie.
+fn stratified_sample(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut strata: HashMap<_, Vec<(u64, u32, PathBuf)>> = HashMap::new();
+    for path in paths {
+        let size = archive::len(&path).unwrap();
+        let size_class = u64::BITS - size.leading_zeros();
+        strata
+            .entry((size_class, color_type(&path)))
+            .or_default()
+            .push((size, 0, path));
+    }
+
+    let mut sample = Vec::new();
+    for mut stratum in strata.into_values() {
+        stratum.sort_by_key(|&(size, _, _)| size);
+        for group in stratum.chunk_by_mut(|a, b| a.0 == b.0) {
+            if group.len() > 1 {
+                for (_, hash, path) in group.iter_mut() {
+                    *hash = crc32fast::hash(&archive::read(path).unwrap());
+                }
+            }
+        }
+        stratum.sort_by_key(|&(size, hash, _)| (size, hash));
+        let count = stratum.len().div_ceil(FAST_SAMPLE_RATE);
+        for i in 0..count {
+            let index = (2 * i + 1) * stratum.len() / (2 * count);
+            sample.push(stratum[index].2.clone());
+        }
+    }
+    sample
+}
+
+/// Selects roughly one in `FAST_SAMPLE_RATE` frame sequences, spread evenly through them in sorted
+/// order, and keeps every frame of each. A sequence is the directory its frames are in, and is
+/// measured from one frame to the next, so sampling single frames would leave nothing to measure.
+fn sample_sequences(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut sequences: Vec<&Path> = paths.iter().filter_map(|path| path.parent()).collect();
+    sequences.sort();
+    sequences.dedup();
+    let count = sequences.len().div_ceil(FAST_SAMPLE_RATE);
+    let chosen: Vec<PathBuf> = (0..count)
+        .map(|i| sequences[(2 * i + 1) * sequences.len() / (2 * count)].to_owned())
+        .collect();
+    paths
+        .into_iter()
+        .filter(|path| {
+            path.parent()
+                .is_some_and(|parent| chosen.iter().any(|c| c == parent))
+        })
+        .collect()
+}
+
+/// Reads the color type from the header of the image at `path`, if it can be decoded, without
+/// reading the rest of the file.
+fn color_type(path: &Path) -> Option<ColorType> {
+    let reader = image::io::Reader::new(archive::open_file(path).ok()?)
+        .with_guessed_format()
+        .ok()?;
+    Some(reader.into_decoder().ok()?.color_type())
+}
+
+fn main() {
+    let args = Args::parse();
+    VERBOSITY
+        .set(if args.quiet {
+            Verbosity::Quiet
+        } else if args.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        })
+        .unwrap();
+
+    if args.drop_page_cache {
+        if !matches!(args.mode, Mode::DecodeIo) {
+            eprintln!("--drop-page-cache can only be used with the decode-io mode");
+            std::process::exit(2);
+        }
+        if !cfg!(target_os = "linux") {
+            eprintln!("--drop-page-cache is only supported on Linux");
+            std::process::exit(2);
+        }
+    }
+
+    // The report is written to stdout, and interleaving the events with it would break both.
+    if args.events.as_deref() == Some(Path::new("-")) {
+        eprintln!(
+            "--events can't write to stdout, which holds the report; try --events /dev/stderr"
+        );
+        std::process::exit(2);
+    }
+
+    // Printed even with --quiet, since the output may be all that is kept of the run.
+    if let Some(name) = &args.name {
+        println!("Run: {}", name);
+    }
+    for note in &args.notes {
+        println!("Note: {}", note);
+    }
+
+    let mode = args.mode.to_possible_value().unwrap().get_name().to_owned();
+    let mut runs = Vec::new();
+    let (mut unit, mut show_ratio) = (Unit::Megapixels, false);
+    let (mut seed, mut corpus) = (None, Vec::new());
+    let (mut wall_clock, mut fingerprint) = (None, None);
+    let mut merged_metadata = None;
+    if args.merge.is_empty() {
+        check_build(args.allow_unoptimized);
+
+        if !args.no_shuffle {
+            let chosen = args.seed.unwrap_or_else(|| rand::thread_rng().gen());
+            info!("Corpus shuffle seed: {}", chosen);
+            seed = Some(chosen);
+        }
+        if let Some(path) = &args.events {
+            events::open(path);
+        }
+        check_corpus(&args.corpus, args.archive.as_deref());
+        if let Some(path) = &args.archive {
+            archive::open(path, Path::new(args.corpus.directory()));
+            if args.drop_page_cache && archive::in_memory() {
+                eprintln!("--drop-page-cache can't be used with a compressed archive, which is read from memory");
+                std::process::exit(2);
+            }
+        }
+        if args.update_lock {
+            lock::update(&args.corpus);
+        }
+        fingerprint = Some(lock::check(&args.corpus, args.allow_lock_mismatch));
+    } else {
+        let (results, metadata);
+        (results, unit, show_ratio, metadata) =
+            results_file::merge(&args.merge, &mode, &args.corpus.name(), args.force_merge);
+        runs.push(results);
+        merged_metadata = Some(metadata);
+    }
+
+    // Checked before running, rather than when comparing, so that a long run isn't wasted. Merged
+    // results are checked with what their files record rather than with this binary and corpus.
+    let (compared_fingerprint, configuration) = match &merged_metadata {
+        Some(merged) => (
+            merged.get("fingerprint").map(String::as_str),
+            ["build", "features", "versions"]
+                .into_iter()
+                .filter_map(|key| Some((key, merged.get(key)?.clone())))
+                .collect(),
+        ),
+        None => (
+            fingerprint.as_deref(),
+            vec![
+                ("build", build_description()),
+                ("features", enabled_features()),
+                ("versions", component_versions()),
+            ],
+        ),
+    };
+    for path in args.baseline.iter().chain(&args.compare_arch) {
+        let (metadata, _) = results_file::read(path);
+        results_file::check_comparable(
+            path,
+            &metadata,
+            compared_fingerprint,
+            &configuration,
+            args.allow_corpus_drift,
+        );
+    }
+
+    if args.merge.is_empty() {
+        corpus = args.corpus.get_corpus(seed, args.fast, args.shard);
+
+        if args.dry_run {
+            dry_run(&args, &corpus);
+            return;
+        }
+        if args.filter_stats {
+            if !matches!(args.mode, Mode::Encode) {
+                eprintln!("--filter-stats can only be used with the encode mode");
+                std::process::exit(2);
+            }
+            filters::print(&corpus);
+            return;
+        }
+        if let [a, b] = &args.paired[..] {
+            if !matches!(args.mode, Mode::Encode) {
+                eprintln!("--paired can only be used with the encode mode");
+                std::process::exit(2);
+            }
+            let encoder = |name: &str| {
+                let Some(&(_, write)) = ENCODERS.iter().find(|(n, _)| *n == name) else {
+                    let names: Vec<&str> = ENCODERS.iter().map(|(n, _)| *n).collect();
+                    eprintln!(
+                        "Unknown encoder '{}', expected one of: {}",
+                        name,
+                        names.join(", ")
+                    );
+                    std::process::exit(2);
+                };
+                write
+            };
+            let budget = args.time_budget.map(Duration::from_secs_f64);
+            let ratios = measure_paired(
+                &corpus,
+                budget,
+                (encoder(a), encoder(b)),
+                args.paired_rounds,
+            );
+            results::print_paired(a, b, &ratios, args.paired_rounds);
+            return;
+        }
+        if let Some(path) = &args.pin_sizes {
+            if !matches!(args.mode, Mode::Encode) {
+                eprintln!("--pin-sizes can only be used with the encode mode");
+                std::process::exit(2);
+            }
+            let results = measure_ratios(&corpus);
+            if !path.exists() {
+                pins::write(path, &results);
+                return;
+            }
+            let changed = pins::compare(path, &results);
+            if args.update_pins {
+                pins::write(path, &results);
+            } else if changed {
+                std::process::exit(1);
+            }
+            return;
+        }
+        if args.ratio_only {
+            if !matches!(args.mode, Mode::Encode) {
+                eprintln!("--ratio-only can only be used with the encode mode");
+                std::process::exit(2);
+            }
+            let results = measure_ratios(&corpus);
+            if let Some(path) = &args.results {
+                let metadata =
+                    results_metadata(&args, &mode, Unit::Megapixels, true, seed, &fingerprint);
+                results_file::write(path, &metadata, &results);
+            }
+            results::print_ratios(&results);
+            results::print_ratio_distribution(&results);
+            if let Some(path) = &args.ratio_histogram {
+                results::write_ratio_histogram(&results, path);
+            }
+            if args.compression_bound {
+                bounds::print(&results);
+            }
+            return;
+        }
+
+        if verbosity() >= Verbosity::Normal {
+            measure_reference();
+        }
+
+        let start = Instant::now();
+        for run in 1..=args.repeat {
+            if args.repeat > 1 {
+                info!("Run {}/{}", run, args.repeat);
+            }
+            let results;
+            (results, unit, show_ratio) = run_benchmark(&args, &corpus);
+            runs.push(results);
+        }
+        wall_clock = Some(start.elapsed());
+    }
+    let mut results = results::combine_runs(&runs);
+    let unit = args.unit.unwrap_or(unit);
+    if let Some(order) = args.sort {
+        results::sort(&mut results, order, unit);
+    }
+
+    results::print_summary(&results, unit, show_ratio);
+    if let Some(path) = &args.results {
+        // Merged results were measured by whichever binaries wrote the files being merged, so
+        // they keep those files' description rather than this binary's.
+        let mut metadata = match &merged_metadata {
+            Some(merged) => merged
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.clone()))
+                .collect(),
+            None => results_metadata(&args, &mode, unit, show_ratio, seed, &fingerprint),
+        };
+        if let Some(wall_clock) = wall_clock {
+            metadata.push(("wall_seconds", format!("{:.1}", wall_clock.as_secs_f64())));
+        }
+        results_file::write(path, &metadata, &results);
+    }
+    if let Some(path) = &args.latency_histogram {
+        results::write_latency_histogram(&results, path);
+    }
+    if let Some(path) = &args.ratio_histogram {
+        if show_ratio {
+            results::write_ratio_histogram(&results, path);
+        } else {
+            eprintln!("No compression ratios to write a histogram of in this mode");
+        }
+    }
+    if let Some(path) = &args.size_curve {
+        size_curve::write(&results, path);
+    }
+    if verbosity() == Verbosity::Quiet {
+        return;
+    }
+    results::print_aggregates(&results, unit);
+    if let Some(profile) = args.profile {
+        results::print_weighted(&results, unit, profile);
+    }
+    results::print_spread(&results, unit);
+    if args.size_curve.is_some() {
+        size_curve::print(&results);
+    }
+    if show_ratio {
+        results::print_ratio_distribution(&results);
+    }
+    if matches!(args.mode, Mode::Optimize) {
+        results::print_size_reduction(&results);
+    }
+    if args.compression_bound && show_ratio {
+        bounds::print(&results);
+    }
+    if matches!(args.mode, Mode::EncodeSink) {
+        results::print_variant_cost(
+            &results,
+            "to counting sink",
+            "to Vec",
+            "Extra time spent building a Vec rather than writing to a counting sink:",
+        );
+    }
+    if matches!(args.mode, Mode::EncodeMetadata) {
+        results::print_variant_cost(
+            &results,
+            "bare",
+            "with metadata",
+            "Extra time spent writing metadata:",
+        );
+    }
+    if matches!(args.mode, Mode::Overhead) {
+        results::print_overhead(&results);
+    }
+    if matches!(args.mode, Mode::Truncated) {
+        results::print_truncation(&results);
+    }
+    if matches!(args.mode, Mode::Decode | Mode::Truncated) {
+        results::print_latency(&results);
+    }
+    if let Some(wall_clock) = wall_clock {
+        results::print_time_spent(&runs, wall_clock);
+    }
+    results::print_peak_rss(&results);
+    results::print_output_buffers(&results);
+    #[cfg(feature = "count-allocations")]
+    results::print_allocations(&results);
+    results::print_speedup_matrix(&results);
+    if let Some(reference) = &args.reference {
+        results::print_significance(&results, reference);
+    }
+    if runs.len() > 1 {
+        results::print_stability(&runs, unit);
+    }
+    if let Some(path) = &args.compare_arch {
+        let (metadata, other) = results_file::read(path);
+        for (key, expected) in [("mode", &mode), ("corpus", &args.corpus.name())] {
+            if metadata.get(key) != Some(expected) {
+                eprintln!(
+                    "{} holds results for {} {:?}, not {:?}",
+                    path.display(),
+                    key,
+                    metadata.get(key).map_or("unknown", String::as_str),
+                    expected
+                );
+                std::process::exit(1);
+            }
+        }
+        // Results saved before runs were tagged with their architecture only record it as part
+        // of the SIMD support.
+        let other_arch = metadata
+            .get("arch")
+            .or(metadata.get("simd"))
+            .and_then(|arch| arch.split_whitespace().next())
+            .unwrap_or("unknown");
+        results::print_arch_comparison(&results, std::env::consts::ARCH, &other, other_arch, unit);
+    }
+    if let Some(path) = &args.baseline {
+        let (metadata, baseline) = results_file::read(path);
+        if metadata.get("machine") != Some(&results_file::machine()) {
+            eprintln!(
+                "Warning: {} was measured on a different machine, so timings may not be comparable",
+                path.display()
+            );
+        }
+        if let Some(simd) = metadata.get("simd").filter(|&simd| *simd != runtime_simd()) {
+            eprintln!(
+                "Warning: {} was measured with different SIMD support ({}), so implementations \
+                 that dispatch at runtime may have taken different code paths",
+                path.display(),
+                simd
+            );
+        }
+        results::print_baseline_changes(
+            &results,
+            &baseline,
+            args.time_threshold,
+            args.alpha,
+            show_ratio.then_some(args.size_threshold),
+        );
+        results::print_regressions(&results, &baseline, args.regressions);
+    }
+    if args.cold {
+        info!("Cold-cache run");
+        cache::set_enabled(true);
+        let (cold, _, _) = run_benchmark(&args, &corpus);
+        cache::set_enabled(false);
+        results::print_cold(&results, &cold);
+    }
+}
+
+/// Number of files the dry run checks each implementation on
+const DRY_RUN_FILES: usize = 5;
+
+/// Runs every implementation in every mode once on a few files spread evenly across the range of
+/// file sizes in `corpus`, without timing them, and round-trips the files through every encoder.
+/// Reports anything that panics, skips files that other implementations in the same mode handled,
+/// or produces output that doesn't decode, and exits with an error if anything did. Files a whole
+/// mode skips, such as images without an ICC profile in the icc mode, are only noted.
+fn dry_run(args: &Args, corpus: &[PathBuf]) {
+    let mut by_size = corpus.to_vec();
+    by_size.sort_by_key(|path| archive::len(path).unwrap_or(0));
+    let files: Vec<PathBuf> = (0..DRY_RUN_FILES.min(by_size.len()))
+        .map(|i| by_size[i * by_size.len() / DRY_RUN_FILES.min(by_size.len())].clone())
+        .collect();
+    println!("Dry run on {} files", files.len());
+    results::set_untimed(true);
+
+    let mut failures = 0;
+    for mode in Mode::value_variants() {
+        let name = mode.to_possible_value().unwrap().get_name().to_owned();
+        let args = Args {
+            mode: mode.clone(),
+            time_budget: None,
+            ..args.clone()
+        };
+        let results = std::panic::catch_unwind(|| run_benchmark(&args, &files).0);
+        match results {
+            Ok(results) => {
+                let handled = results.iter().map(|m| m.samples.len()).max().unwrap_or(0);
+                if handled < files.len() {
+                    info!(
+                        "{}: doesn't apply to {} of the {} files",
+                        name,
+                        files.len() - handled,
+                        files.len()
+                    );
+                }
+                for measurement in results {
+                    if measurement.samples.len() < handled {
+                        println!(
+                            "{}: {} only ran on {} of the {} files other implementations ran on",
+                            name,
+                            measurement.name,
+                            measurement.samples.len(),
+                            handled
+                        );
+                        failures += 1;
+                    }
+                }
+            }
+            Err(_) => {
+                println!("{}: panicked", name);
+                failures += 1;
+            }
+        }
+    }
+
+    for path in &files {
+        let Ok(image) = archive::open_image(path) else {
+            continue;
+        };
+        let image = normalize::to_rgb8_or_rgba8(&image);
+        for (name, write) in ENCODERS {
+            let mut buffer = Cursor::new(Vec::new());
+            write(&mut buffer, &image);
+            let decoded = image::load_from_memory(buffer.get_ref());
+            let same_size = |decoded: DynamicImage| {
+                (decoded.width(), decoded.height()) == (image.width(), image.height())
+            };
+            if !decoded.is_ok_and(same_size) {
+                println!("{}: output for {} doesn't decode", name, path.display());
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        println!("Dry run found {} problems", failures);
+        std::process::exit(1);
+    }
+    println!("Dry run passed");
+}
+
+/// Runs the benchmark for the selected mode once, returning the measurements along with the unit
+/// to report them in and whether compression ratios are meaningful.
+fn run_benchmark(args: &Args, corpus: &[PathBuf]) -> (Vec<Measurement>, Unit, bool) {
+    let budget = args.time_budget.map(Duration::from_secs_f64);
+    match args.mode {
+        Mode::Encode => {
+            info!("Running encoding benchmark with corpus: {:?}", args.corpus);
+            let results = ENCODERS
+                .iter()
+                .map(|&(name, write)| measure_encode(name, corpus, budget, false, write))
+                .collect();
+            (results, Unit::Megapixels, true)
+        }
+        Mode::EncodeReuse => {
+            info!(
+                "Running encoder reuse benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_encode_reuse(corpus, budget), Unit::Megapixels, true)
+        }
+        Mode::EncodeSink => {
+            info!(
+                "Running encoder output sink benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_encode_sink(corpus, budget), Unit::Megapixels, true)
+        }
+        Mode::EncodeMetadata => {
+            info!(
+                "Running encode with metadata benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (
+                measure_encode_metadata(corpus, budget),
+                Unit::Megapixels,
+                true,
+            )
+        }
+        Mode::EncodeLayout => {
+            info!(
+                "Running encoder source layout benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (
+                measure_encode_layout(corpus, budget),
+                Unit::Megapixels,
+                true,
+            )
+        }
+        Mode::Decode => {
+            info!("Running decoding benchmark with corpus: {:?}", args.corpus);
+            let mut results = measure_decode_qoi(corpus, budget);
+            results.extend(measure_decode_webp(corpus, budget));
+            results.extend(measure_decode_original(corpus, budget));
+            (results, Unit::Megapixels, false)
+        }
+        Mode::Differential => {
+            info!(
+                "Running differential decoding benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (
+                measure_differential(corpus, budget),
+                Unit::Megapixels,
+                false,
+            )
+        }
+        Mode::DecodeIo => {
+            info!(
+                "Running I/O-inclusive decoding benchmark with corpus: {:?}",
+                args.corpus
+            );
+            let results = measure_decode_io(corpus, budget, args.drop_page_cache);
+            (results, Unit::Megapixels, false)
+        }
+        Mode::Truncated => {
+            info!(
+                "Running truncated input benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_truncated(corpus, budget), Unit::Megapixels, false)
+        }
+        Mode::DecodeStream => {
+            info!(
+                "Running streaming decode benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (
+                measure_decode_stream(corpus, budget),
+                Unit::Megapixels,
+                false,
+            )
+        }
+        Mode::Transcode => {
+            info!(
+                "Running transcoding benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_transcode(corpus, budget), Unit::Megapixels, true)
+        }
+        Mode::Optimize => {
+            info!(
+                "Running PNG optimizer benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_optimize(corpus, budget), Unit::Megapixels, true)
+        }
+        Mode::Frames => {
+            info!(
+                "Running frame sequence benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_frames(corpus, budget), Unit::Megapixels, false)
+        }
+        Mode::Probe => {
+            info!("Running probing benchmark with corpus: {:?}", args.corpus);
+            (measure_probe(corpus, budget), Unit::Files, false)
+        }
+        Mode::Metadata => {
+            info!("Running metadata benchmark with corpus: {:?}", args.corpus);
+            (measure_metadata(corpus, budget), Unit::Files, false)
+        }
+        Mode::Thumbnail => {
+            info!("Running thumbnail benchmark with corpus: {:?}", args.corpus);
+            let results = measure_decode_thumbnail(corpus, budget);
+            (results, Unit::Megapixels, false)
+        }
+        Mode::Resize => {
+            info!("Running resize benchmark with corpus: {:?}", args.corpus);
+            (measure_resize(corpus, budget), Unit::Megapixels, false)
+        }
+        Mode::Limits => {
+            info!(
+                "Running decoder limits benchmark with corpus: {:?}",
+                args.corpus
+            );
+            let results = measure_decode_limits(corpus, budget);
+            (results, Unit::Megapixels, false)
+        }
+        Mode::Icc => {
+            info!(
+                "Running ICC conversion benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_icc(corpus, budget), Unit::Megapixels, false)
+        }
+        Mode::Linear => {
+            info!(
+                "Running linear decode benchmark with corpus: {:?}",
+                args.corpus
+            );
+            let results = measure_decode_linear(corpus, budget);
+            (results, Unit::Megapixels, false)
+        }
+        Mode::DecodeHdr => {
+            info!(
+                "Running HDR decoding benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_decode_hdr(corpus, budget), Unit::Megapixels, false)
+        }
+        Mode::Uncompressed => {
+            info!(
+                "Running uncompressed format benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_uncompressed(corpus, budget), Unit::Megapixels, true)
+        }
+        Mode::Orientation => {
+            info!(
+                "Running orientation benchmark with corpus: {:?}",
+                args.corpus
+            );
+            let results = measure_decode_orientation(corpus, budget);
+            (results, Unit::Megapixels, false)
+        }
+        Mode::Overhead => {
+            info!(
+                "Running harness overhead benchmark with corpus: {:?}",
+                args.corpus
+            );
+            (measure_overhead(corpus, budget), Unit::Files, false)
+        }
+    }
+}
+
+/// The optimization level, RUSTFLAGS and enabled target features corpus-bench was built with.
+/// Describes the run for the header of a `--results` file.
+fn results_metadata(
+    args: &Args,
+    mode: &str,
+    unit: Unit,
+    show_ratio: bool,
+    seed: Option<u64>,
+    fingerprint: &Option<String>,
+) -> Vec<(&'static str, String)> {
+    let mut metadata = vec![
+        ("mode", mode.to_owned()),
+        ("corpus", args.corpus.name()),
+        ("machine", results_file::machine()),
+        ("build", build_description()),
+        ("features", enabled_features()),
+        ("arch", std::env::consts::ARCH.to_owned()),
+        ("simd", runtime_simd()),
+        ("versions", component_versions()),
+        (
+            "unit",
+            unit.to_possible_value().unwrap().get_name().to_owned(),
+        ),
+        ("ratio", show_ratio.to_string()),
+    ];
+    if let Some(shard) = args.shard {
+        metadata.push(("shard", shard.to_string()));
+    }
+    if let Some(seed) = seed {
+        metadata.push(("seed", seed.to_string()));
+    }
+    if let Some(fingerprint) = fingerprint {
+        metadata.push(("fingerprint", fingerprint.clone()));
+    }
+    metadata
+}
+
+fn build_description() -> String {
+    let opt_level = env!("CORPUS_BENCH_OPT_LEVEL");
+    let rustflags = env!("CORPUS_BENCH_RUSTFLAGS");
+
+    let target_features: Vec<&str> = [
+        ("sse4.1", cfg!(target_feature = "sse4.1")),
+        ("avx", cfg!(target_feature = "avx")),
+        ("avx2", cfg!(target_feature = "avx2")),
+        ("avx512f", cfg!(target_feature = "avx512f")),
+        ("neon", cfg!(target_feature = "neon")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+
+    format!(
+        "opt-level={} debug-assertions={} RUSTFLAGS=\"{}\" target-features=[{}]",
+        opt_level,
+        cfg!(debug_assertions),
+        rustflags,
+        target_features.join(", ")
+    )
+}
+
+/// The optional Cargo features this binary was built with, which decide what gets benchmarked.
+fn enabled_features() -> String {
+    let features: Vec<&str> = [
+        ("libwebp", cfg!(feature = "libwebp")),
+        ("lcms2", cfg!(feature = "lcms2")),
+        ("count-allocations", cfg!(feature = "count-allocations")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+    format!("[{}]", features.join(", "))
+}
+
+/// The resolved versions of the benchmarked crates, and of the C libraries linked in as reported
+/// by the libraries themselves.
+fn component_versions() -> String {
+    let versions = [
+        env!("CORPUS_BENCH_CRATE_VERSIONS").to_owned(),
+        #[cfg(feature = "libwebp")]
+        {
+            // SAFETY: takes no arguments and only returns a constant.
+            let version = unsafe { libwebp_sys::WebPGetDecoderVersion() };
+            format!(
+                "libwebp {}.{}.{}",
+                version >> 16,
+                (version >> 8) & 0xff,
+                version & 0xff
+            )
+        },
+        #[cfg(feature = "lcms2")]
+        {
+            let version = lcms2::version();
+            format!("Little CMS {}.{}", version / 1000, version / 10 % 100)
+        },
+    ];
+    versions.join(", ")
+}
+
+/// The SIMD instruction sets the CPU supports, as detected at runtime. Crates that dispatch at
+/// runtime, like crc32fast and fdeflate, pick their code paths from these rather than from the
+/// target features the binary was compiled with.
+fn runtime_simd() -> String {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let detected = [
+        ("sse2", is_x86_feature_detected!("sse2")),
+        ("ssse3", is_x86_feature_detected!("ssse3")),
+        ("sse4.1", is_x86_feature_detected!("sse4.1")),
+        ("pclmulqdq", is_x86_feature_detected!("pclmulqdq")),
+        ("avx", is_x86_feature_detected!("avx")),
+        ("avx2", is_x86_feature_detected!("avx2")),
+        ("bmi2", is_x86_feature_detected!("bmi2")),
+        ("avx512f", is_x86_feature_detected!("avx512f")),
+        ("avx512bw", is_x86_feature_detected!("avx512bw")),
+    ];
+    #[cfg(target_arch = "aarch64")]
+    let detected = [
+        ("neon", std::arch::is_aarch64_feature_detected!("neon")),
+        ("crc", std::arch::is_aarch64_feature_detected!("crc")),
+        ("aes", std::arch::is_aarch64_feature_detected!("aes")),
+        ("sve", std::arch::is_aarch64_feature_detected!("sve")),
+    ];
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    let detected: [(&str, bool); 0] = [];
+
+    let detected: Vec<&str> = detected
+        .into_iter()
+        .filter_map(|(name, available)| available.then_some(name))
+        .collect();
+    format!("{} [{}]", std::env::consts::ARCH, detected.join(", "))
+}
+
+/// Prints the settings this binary was compiled with, and exits if it was built without
+/// optimizations unless `allow_unoptimized` is set.
+fn check_build(allow_unoptimized: bool) {
+    info!("Build: {}", build_description());
+    info!("Runtime SIMD: {}", runtime_simd());
+    info!("Versions: {}", component_versions());
+
+    if env!("CORPUS_BENCH_OPT_LEVEL") == "0" && !allow_unoptimized {
+        eprintln!(
+            "corpus-bench was compiled without optimizations; rebuild with --release or pass \
+             --allow-unoptimized to run anyway"
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Exits with instructions for getting the corpus if it hasn't been downloaded or generated, rather
+/// than failing partway through listing its files.
+fn check_corpus(corpus: &Corpus, archive: Option<&Path>) {
+    if let Some(archive) = archive {
+        if !archive.is_file() {
+            eprintln!("Corpus archive {} does not exist", archive.display());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let directory = Path::new(corpus.directory());
+    let has_files = WalkDir::new(directory)
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|entry| entry.file_type().is_file());
+    if !has_files {
+        let command = match corpus {
+            Corpus::QoiBench => "setup",
+            Corpus::Synthetic => "generate-synthetic",
+            Corpus::Frames => "generate-synthetic --frames <N>",
+            Corpus::Hdr => "generate-synthetic --hdr",
+        };
+        eprintln!(
+            "Corpus {} has no files in {}; run `cargo xtask {}` to create it",
+            corpus.name(),
+            directory.display(),
+            command
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Size of the buffer used for the memcpy and checksum reference measurements
+const REFERENCE_BUFFER_SIZE: usize = 64 << 20;
+
+/// Measures memcpy and CRC-32 throughput on this machine, as an absolute point of reference for
+/// the codec numbers and as a canary for noisy or throttled runs.
+fn measure_reference() {
+    let mut source = vec![0; REFERENCE_BUFFER_SIZE];
+    rand::thread_rng().fill_bytes(&mut source);
+    let mut destination = vec![0; REFERENCE_BUFFER_SIZE];
+    destination.copy_from_slice(&source);
+
+    let iterations = 8;
+    let megabytes = (iterations * REFERENCE_BUFFER_SIZE) as f64 / (1 << 20) as f64;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        destination.copy_from_slice(black_box(&source));
+        black_box(&mut destination);
+    }
+    let memcpy_time = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(crc32fast::hash(black_box(&source)));
+    }
+    let crc32_time = start.elapsed();
+
+    info!(
+        "memcpy:        {:>6.0} MiB/s",
+        megabytes / memcpy_time.as_secs_f64()
+    );
+    info!(
+        "crc32:         {:>6.0} MiB/s",
+        megabytes / crc32_time.as_secs_f64()
+    );
+}
+
+/// Iterates over the corpus until the timed regions since the iteration started add up to
+/// `budget`.
+fn budgeted(corpus: &[PathBuf], budget: Option<Duration>) -> impl Iterator<Item = &PathBuf> {
+    results::start_pass();
+    corpus
+        .iter()
+        .take_while(move |_| budget.is_none_or(|budget| results::timed_in_pass() < budget))
+}
+
+/// Times `f` encoding every image in the corpus. If `reuse_buffer` is set, the same output buffer
+/// is cleared and reused for every image instead of starting from an empty one.
+fn measure_encode<F: FnMut(&mut Cursor<Vec<u8>>, &DynamicImage)>(
+    name: &str,
+    corpus: &[PathBuf],
+    budget: Option<Duration>,
+    reuse_buffer: bool,
+    mut f: F,
+) -> Measurement {
+    let mut measurement = Measurement::new(name);
+    let mut buffer = Cursor::new(Vec::new());
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(image) = archive::open_image(path) {
+            if image.width() > 16383 || image.height() > 16383 {
+                continue;
+            }
+
+            let image = normalize::to_rgb8_or_rgba8(&image);
+
+            if reuse_buffer {
+                buffer.get_mut().clear();
+                buffer.set_position(0);
+            } else {
+                buffer = Cursor::new(Vec::new());
+            }
+
+            let start = Timer::start();
+            f(&mut buffer, &image);
+            let elapsed = start.elapsed();
+
+            let output = buffer.get_ref();
+            let sample = measurement.record(path, elapsed, &image, output.len());
+            if !reuse_buffer {
+                sample.output_buffer = Some(OutputBuffer::of(output));
+            }
+        }
+    }
+
+    measurement
+}
+
+/// Writes an 8-bit RGB or RGBA image to the buffer in some format.
+type EncodeFn = fn(&mut Cursor<Vec<u8>>, &DynamicImage);
+
+/// The encoders compared by the encode benchmark
+const ENCODERS: [(&str, EncodeFn); 6] = [
+    ("zune-qoi", zune_qoi_write),
+    ("zune-png", zune_png_write),
+    ("mtpng", |buffer, image| {
+        mtpng_write(buffer, image, None, &[])
+    }),
+    ("image-rs QOI", |buffer, image| {
+        image.write_to(buffer, ImageFormat::Qoi).unwrap()
+    }),
+    ("image-rs PNG", |buffer, image| {
+        image.write_to(buffer, ImageFormat::Png).unwrap()
+    }),
+    ("image-rs WebP", |buffer, image| {
+        image.write_to(buffer, ImageFormat::WebP).unwrap()
+    }),
+];
+
+/// Computes the compressed size of every file with every encoder, spread across all cores and
+/// without timing anything, for when only compression ratios are of interest.
+fn measure_ratios(corpus: &[PathBuf]) -> Vec<Measurement> {
+    // Only the counts are kept, so that at most one decoded image per thread is held at a time.
+    let files: Vec<(&PathBuf, u64, u64, Vec<usize>)> = corpus
+        .par_iter()
+        .filter_map(|path| {
+            let image = archive::open_image(path).ok()?;
+            if image.width() > 16383 || image.height() > 16383 {
+                return None;
+            }
+            let image = normalize::to_rgb8_or_rgba8(&image);
+            let sizes = ENCODERS
+                .iter()
+                .map(|(_, write)| {
+                    let mut buffer = Cursor::new(Vec::new());
+                    write(&mut buffer, &image);
+                    buffer.into_inner().len()
+                })
+                .collect();
+            let pixels = image.width() as u64 * image.height() as u64;
+            Some((path, pixels, image.as_bytes().len() as u64, sizes))
+        })
+        .collect();
+
+    ENCODERS
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| {
+            let mut measurement = Measurement::new(*name);
+            for &(path, pixels, raw_bytes, ref sizes) in &files {
+                measurement.samples.push(Sample {
+                    path: path.to_path_buf(),
+                    nanos: 0,
+                    pixels,
+                    raw_bytes,
+                    compressed_bytes: sizes[i] as u64,
+                    allocations: None,
+                    peak_rss_growth: None,
+                    output_buffer: None,
+                    outcome: None,
+                });
+            }
+            measurement
+        })
+        .collect()
+}
+
+/// Encodes `image` with mtpng, using `thread_pool` if given or rayon's global pool otherwise, and
+/// writes `chunks` between the header and image data.
+fn mtpng_write<W: Write>(
+    buffer: W,
+    image: &DynamicImage,
+    thread_pool: Option<&rayon::ThreadPool>,
+    chunks: &[Chunk],
+) {
+    let mut options = mtpng::encoder::Options::new();
+    options
+        .set_compression_level(mtpng::CompressionLevel::Fast)
+        .unwrap();
+    if let Some(thread_pool) = thread_pool {
+        options.set_thread_pool(thread_pool).unwrap();
+    }
+    let mut header = mtpng::Header::new();
+    header.set_size(image.width(), image.height()).unwrap();
+    header
+        .set_color(
+            if image.color().has_alpha() {
+                mtpng::ColorType::TruecolorAlpha
+            } else {
+                mtpng::ColorType::Truecolor
+            },
+            8,
+        )
+        .unwrap();
+
+    let mut encoder = mtpng::encoder::Encoder::new(buffer, &options);
+    encoder.write_header(&header).unwrap();
+    for (name, data) in chunks {
+        encoder.write_chunk(name, data).unwrap();
+    }
+    encoder.write_image_rows(image.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+}
+
+fn zune_png_write(buffer: &mut Cursor<Vec<u8>>, image: &DynamicImage) {
+    let mut encoder = zune_png::PngEncoder::new(
+        image.as_bytes(),
+        zune_png::zune_core::options::EncoderOptions::new(
+            image.width() as usize,
+            image.height() as usize,
+            if image.color().has_alpha() {
+                zune_png::zune_core::colorspace::ColorSpace::RGBA
+            } else {
+                zune_png::zune_core::colorspace::ColorSpace::RGB
+            },
+            zune_png::zune_core::bit_depth::BitDepth::Eight,
+        ),
+    );
+    encoder.encode(buffer).unwrap();
+}
+
+fn zune_qoi_write(buffer: &mut Cursor<Vec<u8>>, image: &DynamicImage) {
+    let mut encoder = zune_qoi::QoiEncoder::new(
+        image.as_bytes(),
+        zune_qoi::zune_core::options::EncoderOptions::new(
+            image.width() as usize,
+            image.height() as usize,
+            if image.color().has_alpha() {
+                zune_qoi::zune_core::colorspace::ColorSpace::RGBA
+            } else {
+                zune_qoi::zune_core::colorspace::ColorSpace::RGB
+            },
+            zune_qoi::zune_core::bit_depth::BitDepth::Eight,
+        ),
+    );
+    let encoded = encoder.encode().unwrap();
+    // Hand over zune-qoi's own buffer when there is nothing to append it to, so that the capacity
+    // it reserved is what gets reported rather than that of a copy.
+    if buffer.get_ref().is_empty() {
+        let len = encoded.len() as u64;
+        *buffer = Cursor::new(encoded);
+        buffer.set_position(len);
+    } else {
+        buffer.write_all(&encoded).unwrap();
+    }
+}
+
+/// The buffer holding the pixels of an image decoded by image-rs.
+fn image_output_buffer(image: DynamicImage) -> Option<OutputBuffer> {
+    Some(match image {
+        DynamicImage::ImageLuma8(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageLumaA8(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageRgb8(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageRgba8(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageLuma16(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageLumaA16(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageRgb16(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageRgba16(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageRgb32F(image) => OutputBuffer::of(&image.into_raw()),
+        DynamicImage::ImageRgba32F(image) => OutputBuffer::of(&image.into_raw()),
+        _ => return None,
+    })
+}
+
+/// The buffer holding the pixels of an image decoded by zune-png.
+fn zune_png_output_buffer(
+    pixels: &zune_png::zune_core::result::DecodingResult,
+) -> Option<OutputBuffer> {
+    use zune_png::zune_core::result::DecodingResult;
+    match pixels {
+        DecodingResult::U8(pixels) => Some(OutputBuffer::of(pixels)),
+        DecodingResult::U16(pixels) => Some(OutputBuffer::of(pixels)),
+        _ => None,
+    }
+}
+
+fn measure_decode_original(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs = Measurement::new("image-rs PNG");
+    let mut zune_png = Measurement::new("zune-png");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let start = Timer::start();
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+            let elapsed = start.elapsed();
+
+            let start2 = Timer::start();
+            let mut decoder = zune_png::PngDecoder::new(Cursor::new(&bytes));
+            decoder.set_options(
+                zune_png::zune_core::options::DecoderOptions::new_fast()
+                    .set_max_width(usize::MAX)
+                    .set_max_height(usize::MAX),
+            );
+            let pixels = black_box(decoder.decode().unwrap());
+            let elapsed2 = start2.elapsed();
+
+            zune_png
+                .record(path, elapsed2, &image, bytes.len())
+                .output_buffer = zune_png_output_buffer(&pixels);
+            let sample = image_rs.record(path, elapsed, &image, bytes.len());
+            sample.output_buffer = image_output_buffer(image);
+        }
+    }
+
+    vec![image_rs, zune_png]
+}
+
+fn measure_decode_webp(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs = Measurement::new("image-rs WebP");
+    #[cfg(feature = "libwebp")]
+    let mut libwebp = Measurement::new("libwebp");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+            if image.width() > 16383 || image.height() > 16383 {
+                continue;
+            }
+            let image = normalize::to_rgb8_or_rgba8(&image);
+
+            let mut encoded = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut encoded), ImageFormat::WebP)
+                .unwrap();
+
+            let start = Timer::start();
+            black_box(image::load_from_memory(&encoded).unwrap());
+            let elapsed = start.elapsed();
+
+            #[cfg(feature = "libwebp")]
+            {
+                let start2 = Timer::start();
+                black_box(webp::Decoder::new(&encoded).decode().unwrap());
+                let elapsed2 = start2.elapsed();
+                libwebp.record(path, elapsed2, &image, encoded.len());
+            }
+
+            image_rs.record(path, elapsed, &image, encoded.len());
+        }
+    }
+
+    vec![
+        image_rs,
+        #[cfg(feature = "libwebp")]
+        libwebp,
+    ]
+}
+
+fn measure_decode_qoi(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs = Measurement::new("image-rs QOI");
+    let mut zune_qoi = Measurement::new("zune-qoi");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+            let image = normalize::to_rgb8_or_rgba8(&image);
+
+            let mut encoded = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut encoded), ImageFormat::Qoi)
+                .unwrap();
+
+            let start = Timer::start();
+            let decoded = black_box(image::load_from_memory(&encoded).unwrap());
+            let elapsed = start.elapsed();
+
+            let start2 = Timer::start();
+            let mut decoder = zune_qoi::QoiDecoder::new_with_options(
+                &encoded,
+                zune_qoi::zune_core::options::DecoderOptions::new_fast()
+                    .set_max_width(usize::MAX)
+                    .set_max_height(usize::MAX),
+            );
+            let pixels = black_box(decoder.decode().unwrap());
+            let elapsed2 = start2.elapsed();
+
+            image_rs
+                .record(path, elapsed, &image, encoded.len())
+                .output_buffer = image_output_buffer(decoded);
+            zune_qoi
+                .record(path, elapsed2, &image, encoded.len())
+                .output_buffer = Some(OutputBuffer::of(&pixels));
+        }
+    }
+
+    vec![image_rs, zune_qoi]
+}
+
+/// Times reading each file and decoding it with image-rs and zune-png. With `drop_page_cache`,
+/// each read has to go to disk rather than being served from memory.
+fn measure_decode_io(
+    corpus: &[PathBuf],
+    budget: Option<Duration>,
+    drop_page_cache: bool,
+) -> Vec<Measurement> {
+    let mut image_rs = Measurement::new("image-rs PNG");
+    let mut zune_png = Measurement::new("zune-png");
+
+    for path in budgeted(corpus, budget) {
+        let Ok(image) = archive::open_image(path) else {
+            continue;
+        };
+        let len = archive::len(path).unwrap() as usize;
+
+        if drop_page_cache {
+            cache::drop_page_cache(path);
+        }
+        let start = Timer::start();
+        black_box(image::load_from_memory(&archive::read(path).unwrap()).unwrap());
+        image_rs.record(path, start.elapsed(), &image, len);
+
+        if drop_page_cache {
+            cache::drop_page_cache(path);
+        }
+        let start = Timer::start();
+        let bytes = archive::read(path).unwrap();
+        let mut decoder = zune_png::PngDecoder::new(Cursor::new(&bytes));
+        decoder.set_options(
+            zune_png::zune_core::options::DecoderOptions::new_fast()
+                .set_max_width(usize::MAX)
+                .set_max_height(usize::MAX),
+        );
+        black_box(decoder.decode().unwrap());
+        zune_png.record(path, start.elapsed(), &image, len);
+    }
+
+    vec![image_rs, zune_png]
+}
+
+/// How much of each file the truncated input benchmark feeds to the decoders, in percent
+const TRUNCATION_PERCENTS: [usize; 5] = [25, 50, 75, 95, 100];
+
+/// Decodes a file, returning whether it was accepted.
+type AcceptFn = fn(&[u8]) -> bool;
+
+/// The panic hook in place before a `SilencedPanics` replaced it
+type PanicHook = Box<dyn Fn(&std::panic::PanicHookInfo<'_>) + Sync + Send>;
+
+/// Stops panics from being printed until it is dropped, when the previous panic hook is restored.
+struct SilencedPanics(Option<PanicHook>);
+
+impl SilencedPanics {
+    fn new() -> Self {
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        Self(Some(hook))
+    }
+}
+
+impl Drop for SilencedPanics {
+    fn drop(&mut self) {
+        std::panic::set_hook(self.0.take().unwrap());
+    }
+}
+
+/// Times each decoder on every file truncated to each of `TRUNCATION_PERCENTS`, which gives the
+/// time to error for inputs it rejects, and records whether it accepted, rejected or panicked on
+/// each input.
+fn measure_truncated(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let decoders: [(&str, AcceptFn); 2] = [
+        ("image-rs PNG", |bytes| {
+            image::load_from_memory_with_format(bytes, ImageFormat::Png).is_ok()
+        }),
+        ("zune-png", |bytes| {
+            let mut decoder = zune_png::PngDecoder::new(Cursor::new(bytes));
+            decoder.set_options(
+                zune_png::zune_core::options::DecoderOptions::new_fast()
+                    .set_max_width(usize::MAX)
+                    .set_max_height(usize::MAX),
+            );
+            decoder.decode().is_ok()
+        }),
+    ];
+    let mut results = Measurement::grid(&decoders, &TRUNCATION_PERCENTS, |(name, _), percent| {
+        format!("{name} {percent}%")
+    });
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+
+            for (i, (_, decode)) in decoders.iter().enumerate() {
+                for (j, percent) in TRUNCATION_PERCENTS.iter().enumerate() {
+                    let index = i * TRUNCATION_PERCENTS.len() + j;
+                    let input = &bytes[..bytes.len() * percent / 100];
+
+                    // Panics are counted as crashes, so don't print them.
+                    let silenced = SilencedPanics::new();
+                    let start = Timer::start();
+                    let accepted = std::panic::catch_unwind(|| decode(input));
+                    let elapsed = start.elapsed();
+                    drop(silenced);
+
+                    results[index]
+                        .record(path, elapsed, &image, input.len())
+                        .outcome = Some(match accepted {
+                        Ok(true) => Outcome::Accepted,
+                        Ok(false) => Outcome::Rejected,
+                        Err(_) => Outcome::Crashed,
+                    });
+                }
+            }
+        }
+    }
+
+    results
+}
+
+fn measure_differential(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs = Measurement::new("image-rs PNG");
+    let mut zune_png = Measurement::new("zune-png");
+    let mut disagreements = 0;
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            if let Some(disagreement) = differential::compare(&bytes) {
+                differential::save(path, &bytes, disagreement);
+                disagreements += 1;
+                continue;
+            }
+
+            let start = Timer::start();
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+            let elapsed = start.elapsed();
+
+            let start2 = Timer::start();
+            let mut decoder = zune_png::PngDecoder::new(Cursor::new(&bytes));
+            decoder.set_options(
+                zune_png::zune_core::options::DecoderOptions::new_fast()
+                    .set_max_width(usize::MAX)
+                    .set_max_height(usize::MAX),
+            );
+            black_box(decoder.decode().unwrap());
+            let elapsed2 = start2.elapsed();
+
+            image_rs.record(path, elapsed, &image, bytes.len());
+            zune_png.record(path, elapsed2, &image, bytes.len());
+        }
+    }
+
+    info!("Decoders disagreed on {} files", disagreements);
+    vec![image_rs, zune_png]
+}
+
+/// Size of the reads handed out by `ChunkedReader`, similar to what a socket or pipe would return.
+const STREAM_CHUNK_SIZE: usize = 4096;
+
+/// A reader that only implements `Read`, returning at most `STREAM_CHUNK_SIZE` bytes per call, to
+/// model decoding straight off a socket or pipe.
+struct ChunkedReader<'a>(&'a [u8]);
+impl Read for ChunkedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(STREAM_CHUNK_SIZE).min(self.0.len());
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+    }
+}
+
+fn measure_decode_stream(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut png_slice = Measurement::new("png (slice)");
+    let mut png_stream = Measurement::new("png (stream)");
+    let mut qoi_slice = Measurement::new("qoi (slice)");
+    let mut qoi_stream = Measurement::new("qoi (stream)");
+
+    fn png_decode(input: impl Read) -> Vec<u8> {
+        let mut reader = png::Decoder::new(input).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        pixels
+    }
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+
+            // Only PNG files can be given to the png crate as they are.
+            if image::guess_format(&bytes).ok() == Some(ImageFormat::Png) {
+                let start = Timer::start();
+                black_box(png_decode(&bytes[..]));
+                let elapsed = start.elapsed();
+
+                let start2 = Timer::start();
+                black_box(png_decode(ChunkedReader(&bytes)));
+                let elapsed2 = start2.elapsed();
+
+                png_slice.record(path, elapsed, &image, bytes.len());
+                png_stream.record(path, elapsed2, &image, bytes.len());
+            }
+
+            let image = normalize::to_rgb8_or_rgba8(&image);
+            let mut encoded = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut encoded), ImageFormat::Qoi)
+                .unwrap();
+
+            let start = Timer::start();
+            black_box(qoi::decode_to_vec(&encoded).unwrap());
+            let elapsed = start.elapsed();
+
+            let start2 = Timer::start();
+            let mut decoder = qoi::Decoder::from_stream(ChunkedReader(&encoded)).unwrap();
+            black_box(decoder.decode_to_vec().unwrap());
+            let elapsed2 = start2.elapsed();
+
+            qoi_slice.record(path, elapsed, &image, encoded.len());
+            qoi_stream.record(path, elapsed2, &image, encoded.len());
+        }
+    }
+
+    vec![png_slice, png_stream, qoi_slice, qoi_stream]
+}
+
+/// Times decoding `input`, converting it to 8 bits per channel and encoding it with `encode`.
+/// Returns the converted image along with the size of the encoded output.
+fn transcode(
+    input: &[u8],
+    encode: impl FnOnce(&DynamicImage) -> Vec<u8>,
+) -> (results::Timing, DynamicImage, usize) {
+    let start = Timer::start();
+    let image = image::load_from_memory(input).unwrap();
+    let image = normalize::to_rgb8_or_rgba8(&image);
+    let output = black_box(encode(&image));
+    (start.elapsed(), image, output.len())
+}
+
+fn measure_transcode(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut png_webp = Measurement::new("PNG->WebP image-rs");
+    #[cfg(feature = "libwebp")]
+    let mut png_libwebp = Measurement::new("PNG->WebP libwebp");
+    let mut png_avif = Measurement::new("PNG->AVIF image-rs");
+    let mut jpeg_webp = Measurement::new("JPEG->WebP image-rs");
+    #[cfg(feature = "libwebp")]
+    let mut jpeg_libwebp = Measurement::new("JPEG->WebP libwebp");
+
+    let image_rs = |format| {
+        move |image: &DynamicImage| {
+            let mut output = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut output), format)
+                .unwrap();
+            output
+        }
+    };
+    #[cfg(feature = "libwebp")]
+    let libwebp = |image: &DynamicImage| {
+        webp::Encoder::from_image(image)
+            .unwrap()
+            .encode_lossless()
+            .to_vec()
+    };
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+            if image.width() > 16383 || image.height() > 16383 {
+                continue;
+            }
+            let mut jpeg = Vec::new();
+            DynamicImage::from(image.to_rgb8())
+                .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+                .unwrap();
+
+            let (elapsed, image, len) = transcode(&bytes, image_rs(ImageFormat::WebP));
+            png_webp.record(path, elapsed, &image, len);
+            #[cfg(feature = "libwebp")]
+            {
+                let (elapsed, image, len) = transcode(&bytes, libwebp);
+                png_libwebp.record(path, elapsed, &image, len);
+            }
+            let (elapsed, image, len) = transcode(&bytes, image_rs(ImageFormat::Avif));
+            png_avif.record(path, elapsed, &image, len);
+
+            let (elapsed, image, len) = transcode(&jpeg, image_rs(ImageFormat::WebP));
+            jpeg_webp.record(path, elapsed, &image, len);
+            #[cfg(feature = "libwebp")]
+            {
+                let (elapsed, image, len) = transcode(&jpeg, libwebp);
+                jpeg_libwebp.record(path, elapsed, &image, len);
+            }
+        }
+    }
+
+    vec![
+        png_webp,
+        #[cfg(feature = "libwebp")]
+        png_libwebp,
+        png_avif,
+        jpeg_webp,
+        #[cfg(feature = "libwebp")]
+        jpeg_libwebp,
+    ]
+}
+
+/// External PNG optimizers compared by the optimize benchmark, with the arguments that make each
+/// one optimize the file at `{output}`. Every tool starts from a copy of the original file there,
+/// since ect only optimizes in place and some tools leave their output untouched if they can't
+/// shrink it.
+const OPTIMIZERS: [(&str, &[&str]); 4] = [
+    ("oxipng", &["--quiet", "--force", "{output}"]),
+    ("zopflipng", &["-y", "{output}", "{output}"]),
+    ("pngcrush", &["-q", "-ow", "{output}"]),
+    ("ect", &["-quiet", "{output}"]),
+];
+
+fn measure_optimize(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut results: Vec<Measurement> = OPTIMIZERS
+        .iter()
+        .map(|(tool, _)| Measurement::new(*tool))
+        .collect();
+    let mut installed = [true; OPTIMIZERS.len()];
+
+    let directory = std::env::temp_dir().join(format!("corpus-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let output = directory.join("optimized.png");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+
+            for (i, (tool, args)) in OPTIMIZERS.iter().enumerate() {
+                if !installed[i] {
+                    continue;
+                }
+                std::fs::write(&output, &bytes).unwrap();
+                let args = args.iter().map(|&arg| match arg {
+                    "{output}" => output.as_os_str(),
+                    arg => arg.as_ref(),
+                });
+
+                let start = Timer::start();
+                let status = Command::new(tool)
+                    .args(args)
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+                let elapsed = start.elapsed();
+
+                match status {
+                    Ok(status) if status.success() => {
+                        let len = std::fs::metadata(&output).unwrap().len();
+                        results[i].record(path, elapsed, &image, len as usize);
+                    }
+                    Ok(_) => eprintln!("{} failed on {}", tool, path.display()),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        info!("Skipping {}: not installed", tool);
+                        installed[i] = false;
+                    }
+                    Err(e) => panic!("Failed to run {}: {}", tool, e),
+                }
+            }
+        }
+    }
+
+    std::fs::remove_dir_all(&directory).unwrap();
+    results.retain(|m| !m.samples.is_empty());
+    results
+}
+
+/// Encodes and decodes every frame in order, ignoring any shuffling, with the buffers of each
+/// implementation reused from one frame to the next, as a screen recorder or video thumbnailer
+/// would.
+fn measure_frames(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs_encode = Measurement::new("image-rs PNG encode");
+    let mut image_rs_decode = Measurement::new("image-rs PNG decode");
+    let mut zune_png_decode = Measurement::new("zune-png decode");
+
+    let mut frames = corpus.to_vec();
+    frames.sort();
+    let mut encoded = Vec::new();
+    let mut image_rs_pixels = Vec::new();
+    let mut zune_png_pixels = Vec::new();
+
+    for path in budgeted(&frames, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+
+            let start = Timer::start();
+            encoded.clear();
+            image
+                .write_to(&mut Cursor::new(&mut encoded), ImageFormat::Png)
+                .unwrap();
+            black_box(&encoded);
+            image_rs_encode.record(path, start.elapsed(), &image, encoded.len());
+
+            let start = Timer::start();
+            let decoder = image::codecs::png::PngDecoder::new(Cursor::new(&bytes)).unwrap();
+            image_rs_pixels.resize(decoder.total_bytes() as usize, 0);
+            decoder.read_image(&mut image_rs_pixels).unwrap();
+            black_box(&image_rs_pixels);
+            image_rs_decode.record(path, start.elapsed(), &image, bytes.len());
+
+            let start = Timer::start();
+            let mut decoder = zune_png::PngDecoder::new(Cursor::new(&bytes));
+            decoder.decode_headers().unwrap();
+            zune_png_pixels.resize(decoder.output_buffer_size().unwrap(), 0);
+            decoder.decode_into(&mut zune_png_pixels).unwrap();
+            black_box(&zune_png_pixels);
+            zune_png_decode.record(path, start.elapsed(), &image, bytes.len());
+        }
+    }
+
+    vec![image_rs_encode, image_rs_decode, zune_png_decode]
+}
+
+/// Times reading the dimensions, color type and ICC profile from each file's header. zune-png also
+/// reports whether there is EXIF data, but image 0.25.1 has no way to ask its decoders for EXIF,
+/// so the image-rs rows can't check for it yet.
+fn measure_probe(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs_png = Measurement::new("image-rs PNG");
+    let mut zune_png = Measurement::new("zune-png");
+    let mut image_rs_webp = Measurement::new("image-rs WebP");
+    #[cfg(feature = "libwebp")]
+    let mut libwebp = Measurement::new("libwebp");
+    let mut image_rs_jpeg = Measurement::new("image-rs JPEG");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+            if image.width() > 16383 || image.height() > 16383 {
+                continue;
+            }
+            let rgb: DynamicImage = image.to_rgb8().into();
+            let image = normalize::to_rgb8_or_rgba8(&image);
+
+            let mut webp_encoded = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut webp_encoded), ImageFormat::WebP)
+                .unwrap();
+            let mut jpeg_encoded = Vec::new();
+            rgb.write_to(&mut Cursor::new(&mut jpeg_encoded), ImageFormat::Jpeg)
+                .unwrap();
+
+            let start = Timer::start();
+            let mut decoder = image::codecs::png::PngDecoder::new(Cursor::new(&bytes)).unwrap();
+            black_box((
+                decoder.dimensions(),
+                decoder.color_type(),
+                decoder.icc_profile().unwrap(),
+            ));
+            image_rs_png.record(path, start.elapsed(), &image, bytes.len());
+
+            let start = Timer::start();
+            let mut decoder = zune_png::PngDecoder::new(Cursor::new(&bytes));
+            decoder.decode_headers().unwrap();
+            let info = decoder.info().unwrap();
+            black_box((
+                decoder.dimensions(),
+                decoder.colorspace(),
+                info.exif.is_some(),
+                info.icc_profile.is_some(),
+            ));
+            zune_png.record(path, start.elapsed(), &image, bytes.len());
+
+            let start = Timer::start();
+            let mut decoder =
+                image::codecs::webp::WebPDecoder::new(Cursor::new(&webp_encoded)).unwrap();
+            black_box((
+                decoder.dimensions(),
+                decoder.color_type(),
+                decoder.icc_profile().unwrap(),
+            ));
+            image_rs_webp.record(path, start.elapsed(), &image, webp_encoded.len());
+
+            #[cfg(feature = "libwebp")]
+            {
+                let start = Timer::start();
+                let features = webp::BitstreamFeatures::new(&webp_encoded).unwrap();
+                black_box((features.width(), features.height(), features.has_alpha()));
+                libwebp.record(path, start.elapsed(), &image, webp_encoded.len());
+            }
+
+            let start = Timer::start();
+            let mut decoder =
+                image::codecs::jpeg::JpegDecoder::new(Cursor::new(&jpeg_encoded)).unwrap();
+            black_box((
+                decoder.dimensions(),
+                decoder.color_type(),
+                decoder.icc_profile().unwrap(),
+            ));
+            image_rs_jpeg.record(path, start.elapsed(), &image, jpeg_encoded.len());
+        }
+    }
+
+    vec![
+        image_rs_png,
+        zune_png,
+        image_rs_webp,
+        #[cfg(feature = "libwebp")]
+        libwebp,
+        image_rs_jpeg,
+    ]
+}
+
+/// Times the png crate reading every chunk before the image data, which includes decompressing
+/// any ICC profile, with and without text chunks. Then separately times decompressing the text
+/// of any zTXt and iTXt chunks, which the png crate leaves until it is asked for.
+fn measure_metadata(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut read_info = Measurement::new("png read_info");
+    let mut read_info_no_text = Measurement::new("png read_info without text");
+    let mut decompress_text = Measurement::new("png text decompression");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory_with_format(&bytes, ImageFormat::Png) else {
+                continue;
+            };
+
+            let start = Timer::start();
+            let reader = black_box(png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap());
+            read_info.record(path, start.elapsed(), &image, bytes.len());
+            let info = reader.info().clone();
+
+            let start = Timer::start();
+            let mut decoder = png::Decoder::new(Cursor::new(&bytes));
+            decoder.set_ignore_text_chunk(true);
+            black_box(decoder.read_info().unwrap().info());
+            read_info_no_text.record(path, start.elapsed(), &image, bytes.len());
+
+            let (mut ztxt, mut itxt) = (info.compressed_latin1_text, info.utf8_text);
+            let start = Timer::start();
+            for chunk in &mut ztxt {
+                chunk.decompress_text().unwrap();
+            }
+            for chunk in &mut itxt {
+                chunk.decompress_text().unwrap();
+            }
+            black_box((ztxt, itxt));
+            decompress_text.record(path, start.elapsed(), &image, bytes.len());
+        }
+    }
+
+    vec![read_info, read_info_no_text, decompress_text]
+}
+
+/// The maximum width and height of the images produced by the thumbnail benchmark
+const THUMBNAIL_SIZE: u32 = 256;
+
+fn measure_decode_thumbnail(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs_thumbnail = Measurement::new("image-rs thumbnail");
+    let mut image_rs_triangle = Measurement::new("image-rs triangle");
+    let mut zune_png_thumbnail = Measurement::new("zune-png thumbnail");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let start = Timer::start();
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+            black_box(image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE));
+            let elapsed = start.elapsed();
+
+            let start2 = Timer::start();
+            let image = image::load_from_memory(&bytes).unwrap();
+            black_box(image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle));
+            let elapsed2 = start2.elapsed();
+
+            let start3 = Timer::start();
+            let mut decoder = zune_png::PngDecoder::new(Cursor::new(&bytes));
+            decoder.set_options(
+                zune_png::zune_core::options::DecoderOptions::new_fast()
+                    .set_max_width(usize::MAX)
+                    .set_max_height(usize::MAX)
+                    .png_set_strip_to_8bit(true),
+            );
+            let pixels = decoder.decode_raw().unwrap();
+            let (width, height) = (image.width(), image.height());
+            let channels = pixels.len() / (width as usize * height as usize);
+            let decoded = normalize::from_zune(
+                zune_png::zune_core::result::DecodingResult::U8(pixels),
+                width,
+                height,
+                channels,
+            )
+            .unwrap();
+            black_box(decoded.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE));
+            let elapsed3 = start3.elapsed();
+
+            image_rs_thumbnail.record(path, elapsed, &image, bytes.len());
+            image_rs_triangle.record(path, elapsed2, &image, bytes.len());
+            zune_png_thumbnail.record(path, elapsed3, &image, bytes.len());
+        }
+    }
+
+    vec![image_rs_thumbnail, image_rs_triangle, zune_png_thumbnail]
+}
+
+/// The filters compared by the resize benchmark, and the names they are reported under
+const RESIZE_FILTERS: [(FilterType, &str); 3] = [
+    (FilterType::Nearest, "nearest"),
+    (FilterType::Triangle, "bilinear"),
+    (FilterType::Lanczos3, "lanczos3"),
+];
+
+/// The factors the resize benchmark downscales both dimensions by
+const RESIZE_FACTORS: [u32; 2] = [2, 4];
+
+fn measure_resize(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut results = Measurement::grid(&RESIZE_FACTORS, &RESIZE_FILTERS, |factor, (_, name)| {
+        format!("imageops {name} 1/{factor}")
+    });
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+
+            let mut measurements = results.iter_mut();
+            for factor in RESIZE_FACTORS {
+                let width = (image.width() / factor).max(1);
+                let height = (image.height() / factor).max(1);
+                for (filter, _) in RESIZE_FILTERS {
+                    let start = Timer::start();
+                    black_box(image.resize_exact(width, height, filter));
+                    let elapsed = start.elapsed();
+                    measurements
+                        .next()
+                        .unwrap()
+                        .record(path, elapsed, &image, bytes.len());
+                }
+            }
+        }
+    }
+
+    results
 }
 
-/// The mode to run the benchmark in
-#[derive(ValueEnum, Clone, Debug)]
-enum Mode {
-    /// Measure the performance of encoding
-    Encode,
-    /// Measure the performance of decoding
-    Decode,
-}
+fn measure_decode_limits(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs_limited = Measurement::new("image-rs PNG limited");
+    let mut image_rs_unlimited = Measurement::new("image-rs PNG unlimited");
+    let mut zune_png_limited = Measurement::new("zune-png limited");
+    let mut zune_png_unlimited = Measurement::new("zune-png unlimited");
 
-/// The corpus to choose from
-#[derive(ValueEnum, Clone, Debug)]
-enum Corpus {
-    /// The QOI Benchmark corpus
-    QoiBench,
-}
-impl Corpus {
-    fn get_corpus(&self) -> Vec<PathBuf> {
-        let directory = match self {
-            Corpus::QoiBench => "corpus/qoi_benchmark_suite",
-        };
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let start = Timer::start();
+            let Ok(image) = image_rs_decode_limited(&bytes) else {
+                continue;
+            };
+            let elapsed = start.elapsed();
 
-        let mut paths = Vec::new();
-        for entry in WalkDir::new(directory) {
-            let entry = entry.unwrap();
-            if entry.file_type().is_file() {
-                paths.push(entry.path().to_owned());
+            let start2 = Timer::start();
+            black_box(image_rs_decode_unlimited(&bytes).unwrap());
+            let elapsed2 = start2.elapsed();
+
+            let start3 = Timer::start();
+            if zune_png_decode_limited(&bytes).is_err() {
+                continue;
             }
+            let elapsed3 = start3.elapsed();
+
+            let start4 = Timer::start();
+            black_box(zune_png_decode_unlimited(&bytes).unwrap());
+            let elapsed4 = start4.elapsed();
+
+            image_rs_limited.record(path, elapsed, &image, bytes.len());
+            image_rs_unlimited.record(path, elapsed2, &image, bytes.len());
+            zune_png_limited.record(path, elapsed3, &image, bytes.len());
+            zune_png_unlimited.record(path, elapsed4, &image, bytes.len());
         }
+    }
 
-        paths.shuffle(&mut rand::thread_rng());
-        paths
+    // Decoding the bomb without limits takes over 512 MiB, so it is only checked in the first
+    // timed pass rather than in the dry run and every repeated or cold-cache pass.
+    if !results::untimed() {
+        BOMB_CHECK.call_once(check_bomb);
     }
+
+    vec![
+        image_rs_limited,
+        image_rs_unlimited,
+        zune_png_limited,
+        zune_png_unlimited,
+    ]
 }
 
-fn main() {
-    let args = Args::parse();
+/// Whether the decoders' limits have been checked against the decompression bomb
+static BOMB_CHECK: Once = Once::new();
 
-    let corpus = args.corpus.get_corpus();
+/// Checks that each decoder's limits stop it from decoding the decompression bomb.
+fn check_bomb() {
+    let bomb = decompression_bomb();
+    let image_rs_stopped = matches!(
+        image_rs_decode_limited(bomb),
+        Err(image::ImageError::Limits(_))
+    );
+    report_bomb(
+        "image-rs PNG",
+        image_rs_stopped,
+        image_rs_decode_unlimited(bomb).is_ok(),
+    );
+    // zune-png's only limits are its maximum dimensions, and it reports images over them as a
+    // generic error.
+    let zune_png_stopped = matches!(
+        zune_png_decode_limited(bomb),
+        Err(zune_png::error::PngDecodeErrors::Generic(_))
+    );
+    report_bomb(
+        "zune-png",
+        zune_png_stopped,
+        zune_png_decode_unlimited(bomb).is_ok(),
+    );
+}
 
-    match args.mode {
-        Mode::Encode => {
-            println!("Running encoding benchmark with corpus: {:?}", args.corpus);
+/// Reports whether a decoder's limits stopped it from decoding a decompression bomb. Unless the
+/// same decoder without limits could decode the bomb, its limits aren't what stopped it.
+fn report_bomb(name: &str, stopped_by_limits: bool, decodes_without_limits: bool) {
+    match (stopped_by_limits, decodes_without_limits) {
+        (true, true) => info!("{}: limits stopped the decompression bomb", name),
+        (false, true) => eprintln!(
+            "Warning: {}'s limits didn't stop the decompression bomb",
+            name
+        ),
+        (_, false) => eprintln!(
+            "Warning: {} can't decode the decompression bomb even without limits",
+            name
+        ),
+    }
+}
 
-            let (bandwidth, compression_ratio) = zune_qoi_encode(&corpus);
-            println!(
-                "zune-qoi:      {:>6.1} MP/s  {:02.2}%",
-                bandwidth,
-                compression_ratio * 100.0
-            );
+/// Decodes with image-rs and its default limits.
+fn image_rs_decode_limited(bytes: &[u8]) -> image::ImageResult<DynamicImage> {
+    image::io::Reader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .decode()
+}
 
-            let (bandwidth, compression_ratio) = zune_png_encode(&corpus);
-            println!(
-                "zune-png:      {:>6.1} MP/s  {:02.2}%",
-                bandwidth,
-                compression_ratio * 100.0
-            );
+fn image_rs_decode_unlimited(bytes: &[u8]) -> image::ImageResult<DynamicImage> {
+    let mut reader = image::io::Reader::new(Cursor::new(bytes)).with_guessed_format()?;
+    reader.no_limits();
+    reader.decode()
+}
 
-            let (bandwidth, compression_ratio) = mtpng_encode(&corpus);
-            println!(
-                "mtpng:         {:>6.1} MP/s  {:02.2}%",
-                bandwidth,
-                compression_ratio * 100.0
-            );
+fn zune_png_decode_limited(
+    bytes: &[u8],
+) -> Result<zune_png::zune_core::result::DecodingResult, zune_png::error::PngDecodeErrors> {
+    let mut decoder = zune_png::PngDecoder::new(Cursor::new(bytes));
+    decoder.set_options(zune_png::zune_core::options::DecoderOptions::new_fast());
+    decoder.decode()
+}
 
-            let (bandwidth, compression_ratio) = image_rs_encode(&corpus, ImageFormat::Qoi);
-            println!(
-                "image-rs QOI:  {:>6.1} MP/s  {:02.2}%",
-                bandwidth,
-                compression_ratio * 100.0
-            );
+fn zune_png_decode_unlimited(
+    bytes: &[u8],
+) -> Result<zune_png::zune_core::result::DecodingResult, zune_png::error::PngDecodeErrors> {
+    let mut decoder = zune_png::PngDecoder::new(Cursor::new(bytes));
+    decoder.set_options(
+        zune_png::zune_core::options::DecoderOptions::new_fast()
+            .set_max_width(usize::MAX)
+            .set_max_height(usize::MAX)
+            .inflate_set_limit(usize::MAX),
+    );
+    decoder.decode()
+}
 
-            let (bandwidth, compression_ratio) = image_rs_encode(&corpus, ImageFormat::Png);
-            println!(
-                "image-rs PNG:  {:>6.1} MP/s  {:02.2}%",
-                bandwidth,
-                compression_ratio * 100.0
-            );
+/// Width and height of the decompression bomb. The width is just over zune-png's default maximum,
+/// and the RGBA pixels take just over image-rs's default 512 MiB allocation limit.
+const BOMB_SIZE: (u32, u32) = (16385, 8200);
 
-            let (bandwidth, compression_ratio) = image_rs_encode(&corpus, ImageFormat::WebP);
-            println!(
-                "image-rs WebP: {:>6.1} MP/s  {:02.2}%",
-                bandwidth,
-                compression_ratio * 100.0
-            );
-        }
-        Mode::Decode => {
-            println!("Running decoding benchmark with corpus: {:?}", args.corpus);
-            measure_decode_qoi(&corpus);
-            measure_decode_webp(&corpus);
-            measure_decode_original(&corpus);
-        }
+/// Builds a well-formed all-black RGBA PNG that compresses to about half a megabyte but needs over
+/// 512 MiB to decode, so that only a decoder's limits can stop it from being decoded. It is
+/// written a row at a time to avoid holding the pixels in memory, and only the first time it is
+/// needed.
+fn decompression_bomb() -> &'static [u8] {
+    static BOMB: OnceLock<Vec<u8>> = OnceLock::new();
+    BOMB.get_or_init(build_decompression_bomb)
+}
+
+fn build_decompression_bomb() -> Vec<u8> {
+    let (width, height) = BOMB_SIZE;
+    let mut bomb = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bomb, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Fast);
+    let mut writer = encoder.write_header().unwrap();
+    let mut stream = writer.stream_writer().unwrap();
+    let row = vec![0; width as usize * 4];
+    for _ in 0..height {
+        stream.write_all(&row).unwrap();
     }
+    stream.finish().unwrap();
+    writer.finish().unwrap();
+    bomb
 }
 
-fn measure_encode<F: FnMut(&mut Cursor<Vec<u8>>, &DynamicImage)>(
-    corpus: &[PathBuf],
-    mut f: F,
-) -> (f64, f64) {
-    let mut total_time = 0;
-    let mut total_bytes = 0;
-    let mut uncompressed_bytes = 0;
-    let mut total_pixels = 0;
-
-    for path in corpus {
-        if let Ok(image) = image::open(path) {
-            if image.width() > 16383 || image.height() > 16383 {
+/// Times parsing each embedded ICC profile, building a transform to sRGB, and applying it. Files
+/// without an ICC profile are skipped, as is decoding, which is identical for every library.
+fn measure_icc(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut qcms = Measurement::new("qcms");
+    #[cfg(feature = "lcms2")]
+    let mut lcms2 = Measurement::new("lcms2");
+    let mut moxcms = Measurement::new("moxcms");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(mut decoder) = image::codecs::png::PngDecoder::new(Cursor::new(&bytes)) else {
                 continue;
-            }
+            };
+            let Ok(Some(icc)) = decoder.icc_profile() else {
+                continue;
+            };
+            let Ok(image) = DynamicImage::from_decoder(decoder) else {
+                continue;
+            };
+            let pixels = image.to_rgba8().into_raw();
 
-            let image: DynamicImage = if image.color().has_alpha() {
-                image.to_rgba8().into()
-            } else {
-                image.to_rgb8().into()
+            let start = Timer::start();
+            let Some(input) = qcms::Profile::new_from_slice(&icc, false) else {
+                continue;
             };
+            let Some(transform) = qcms::Transform::new(
+                &input,
+                &qcms::Profile::new_sRGB(),
+                qcms::DataType::RGBA8,
+                qcms::Intent::Perceptual,
+            ) else {
+                continue;
+            };
+            let mut output = pixels.clone();
+            transform.apply(&mut output);
+            black_box(output);
+            let elapsed = start.elapsed();
 
-            let mut buffer = Cursor::new(Vec::new());
+            #[cfg(feature = "lcms2")]
+            let elapsed2 = {
+                let start2 = Timer::start();
+                let Ok(input) = lcms2::Profile::new_icc(&icc) else {
+                    continue;
+                };
+                let Ok(transform) = lcms2::Transform::<u8, u8>::new(
+                    &input,
+                    lcms2::PixelFormat::RGBA_8,
+                    &lcms2::Profile::new_srgb(),
+                    lcms2::PixelFormat::RGBA_8,
+                    lcms2::Intent::Perceptual,
+                ) else {
+                    continue;
+                };
+                let mut output = pixels.clone();
+                transform.transform_in_place(&mut output);
+                black_box(output);
+                start2.elapsed()
+            };
 
-            let start = std::time::Instant::now();
-            f(&mut buffer, &image);
-            let elapsed = start.elapsed();
+            let start3 = Timer::start();
+            let Ok(input) = moxcms::ColorProfile::new_from_slice(&icc) else {
+                continue;
+            };
+            let Ok(transform) = input.create_transform_8bit(
+                moxcms::Layout::Rgba,
+                &moxcms::ColorProfile::new_srgb(),
+                moxcms::Layout::Rgba,
+                moxcms::TransformOptions::default(),
+            ) else {
+                continue;
+            };
+            let mut output = vec![0; pixels.len()];
+            transform.transform(&pixels, &mut output).unwrap();
+            black_box(output);
+            let elapsed3 = start3.elapsed();
 
-            total_time += elapsed.as_nanos();
-            total_bytes += buffer.get_ref().len() as u64;
-            uncompressed_bytes += image.as_bytes().len() as u64;
-            total_pixels += image.width() as u64 * image.height() as u64;
+            qcms.record(path, elapsed, &image, bytes.len());
+            #[cfg(feature = "lcms2")]
+            lcms2.record(path, elapsed2, &image, bytes.len());
+            moxcms.record(path, elapsed3, &image, bytes.len());
         }
     }
+    if qcms.samples.is_empty() {
+        info!("No images with usable ICC profiles found in corpus");
+        return Vec::new();
+    }
+    info!("Converted {} images with ICC profiles", qcms.samples.len());
 
-    let bandwidth = (total_pixels as f64 / (1 << 20) as f64) / (total_time as f64 * 1e-9);
-    let compression_ratio = total_bytes as f64 / uncompressed_bytes as f64;
-    (bandwidth, compression_ratio)
+    vec![
+        qcms,
+        #[cfg(feature = "lcms2")]
+        lcms2,
+        moxcms,
+    ]
 }
 
-fn image_rs_encode(corpus: &[PathBuf], format: ImageFormat) -> (f64, f64) {
-    measure_encode(corpus, |buffer, image| {
-        image.write_to(buffer, format).unwrap();
-    })
+/// Applies the sRGB transfer function to a single channel value in the range [0, 1].
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
 }
 
-fn mtpng_encode(corpus: &[PathBuf]) -> (f64, f64) {
-    measure_encode(corpus, |buffer, image| {
-        let mut options = mtpng::encoder::Options::new();
-        options
-            .set_compression_level(mtpng::CompressionLevel::Fast)
-            .unwrap();
-        let mut header = mtpng::Header::new();
-        header
-            .set_size(image.width() as u32, image.height() as u32)
-            .unwrap();
-        header
-            .set_color(
-                if image.color().has_alpha() {
-                    mtpng::ColorType::TruecolorAlpha
-                } else {
-                    mtpng::ColorType::Truecolor
-                },
-                8,
-            )
-            .unwrap();
-
-        let mut encoder = mtpng::encoder::Encoder::new(buffer, &options);
-        encoder.write_header(&header).unwrap();
-        encoder.write_image_rows(&image.as_bytes()).unwrap();
-        encoder.finish().unwrap();
-    })
+/// Converts 8-bit RGBA samples to linear-light f32, leaving alpha unchanged.
+fn rgba8_to_linear(pixels: &[u8], lut: &[f32; 256]) -> Vec<f32> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|p| {
+            [
+                lut[p[0] as usize],
+                lut[p[1] as usize],
+                lut[p[2] as usize],
+                p[3] as f32 / 255.0,
+            ]
+        })
+        .collect()
 }
 
-fn zune_png_encode(corpus: &[PathBuf]) -> (f64, f64) {
-    measure_encode(corpus, |buffer, image| {
-        let mut encoder = zune_png::PngEncoder::new(
-            image.as_bytes(),
-            zune_png::zune_core::options::EncoderOptions::new(
-                image.width() as usize,
-                image.height() as usize,
-                if image.color().has_alpha() {
-                    zune_png::zune_core::colorspace::ColorSpace::RGBA
-                } else {
-                    zune_png::zune_core::colorspace::ColorSpace::RGB
-                },
-                zune_png::zune_core::bit_depth::BitDepth::Eight,
-            ),
-        );
-        encoder.encode(buffer).unwrap();
-    })
+/// Converts 8-bit gray and alpha samples to linear-light f32 RGBA, like `rgba8_to_linear`.
+fn gray_alpha8_to_linear(pixels: &[u8], lut: &[f32; 256]) -> Vec<f32> {
+    pixels
+        .chunks_exact(2)
+        .flat_map(|p| {
+            let gray = lut[p[0] as usize];
+            [gray, gray, gray, p[1] as f32 / 255.0]
+        })
+        .collect()
 }
 
-fn zune_qoi_encode(corpus: &[PathBuf]) -> (f64, f64) {
-    measure_encode(corpus, |buffer, image| {
-        let mut encoder = zune_qoi::QoiEncoder::new(
-            image.as_bytes(),
-            zune_qoi::zune_core::options::EncoderOptions::new(
-                image.width() as usize,
-                image.height() as usize,
-                if image.color().has_alpha() {
-                    zune_qoi::zune_core::colorspace::ColorSpace::RGBA
-                } else {
-                    zune_qoi::zune_core::colorspace::ColorSpace::RGB
-                },
-                zune_qoi::zune_core::bit_depth::BitDepth::Eight,
-            ),
-        );
-        buffer.write_all(&encoder.encode().unwrap()).unwrap()
-    })
-}
+fn measure_decode_linear(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs_powf = Measurement::new("image-rs powf");
+    let mut image_rs_lut = Measurement::new("image-rs LUT");
+    let mut zune_png_lut = Measurement::new("zune-png LUT");
 
-fn measure_decode_original(corpus: &[PathBuf]) {
-    let mut image_rs_total_time = 0;
-    let mut zune_png_total_time = 0;
-    let mut total_pixels = 0;
+    let lut: [f32; 256] = std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0));
+    let mut skipped = 0;
 
-    for path in corpus {
-        if let Ok(bytes) = std::fs::read(path) {
-            let start = std::time::Instant::now();
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let start = Timer::start();
             let Ok(image) = image::load_from_memory(&bytes) else {
                 continue;
             };
+            let mut linear = image.to_rgba32f();
+            for pixel in linear.pixels_mut() {
+                for channel in &mut pixel.0[..3] {
+                    *channel = srgb_to_linear(*channel);
+                }
+            }
+            black_box(linear);
             let elapsed = start.elapsed();
 
-            let start2 = std::time::Instant::now();
-            let mut decoder = zune_png::PngDecoder::new(Cursor::new(bytes));
+            let start2 = Timer::start();
+            let image = image::load_from_memory(&bytes).unwrap();
+            black_box(rgba8_to_linear(&image.to_rgba8(), &lut));
+            let elapsed2 = start2.elapsed();
+
+            let start3 = Timer::start();
+            let mut decoder = zune_png::PngDecoder::new(Cursor::new(&bytes));
             decoder.set_options(
                 zune_png::zune_core::options::DecoderOptions::new_fast()
                     .set_max_width(usize::MAX)
-                    .set_max_height(usize::MAX),
+                    .set_max_height(usize::MAX)
+                    .png_set_strip_to_8bit(true)
+                    .png_set_add_alpha_channel(true),
             );
-            black_box(decoder.decode().unwrap());
-            let elapsed2 = start2.elapsed();
+            let pixels = decoder.decode_raw().unwrap();
+            // Grayscale images only gain an alpha channel, not color channels.
+            let pixel_count = image.width() as usize * image.height() as usize;
+            if pixels.len() == pixel_count * 4 {
+                black_box(rgba8_to_linear(&pixels, &lut));
+            } else if pixels.len() == pixel_count * 2 {
+                black_box(gray_alpha8_to_linear(&pixels, &lut));
+            } else {
+                skipped += 1;
+                continue;
+            }
+            let elapsed3 = start3.elapsed();
 
-            image_rs_total_time += elapsed.as_nanos();
-            zune_png_total_time += elapsed2.as_nanos();
-            total_pixels += image.width() as u64 * image.height() as u64;
+            image_rs_powf.record(path, elapsed, &image, bytes.len());
+            image_rs_lut.record(path, elapsed2, &image, bytes.len());
+            zune_png_lut.record(path, elapsed3, &image, bytes.len());
         }
     }
-    let bandwidth = (total_pixels as f64 / (1 << 20) as f64) / (image_rs_total_time as f64 * 1e-9);
-    println!("image-rs PNG:  {:>6.1} MP/s", bandwidth);
 
-    let bandwidth = (total_pixels as f64 / (1 << 20) as f64) / (zune_png_total_time as f64 * 1e-9);
-    println!("zune-png:      {:>6.1} MP/s", bandwidth);
+    if skipped > 0 {
+        eprintln!(
+            "Warning: skipped {} files that zune-png decoded to an unexpected number of channels",
+            skipped
+        );
+    }
+    vec![image_rs_powf, image_rs_lut, zune_png_lut]
+}
+
+/// Decodes an OpenEXR image with the exr crate to RGBA f32 pixels, optionally using a thread pool
+/// to decompress blocks in parallel.
+fn exr_decode(bytes: &[u8], parallel: bool) -> Vec<f32> {
+    use exr::prelude::{ReadChannels, ReadLayers};
+
+    let read = exr::prelude::read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .rgba_channels(
+            |size, _| (size.width(), vec![0.0; size.area() * 4]),
+            |(width, pixels): &mut (usize, Vec<f32>),
+             position,
+             (r, g, b, a): (f32, f32, f32, f32)| {
+                let i = (position.y() * *width + position.x()) * 4;
+                pixels[i..i + 4].copy_from_slice(&[r, g, b, a]);
+            },
+        )
+        .first_valid_layer()
+        .all_attributes();
+    let read = if parallel { read } else { read.non_parallel() };
+    read.from_buffered(Cursor::new(bytes))
+        .unwrap()
+        .layer_data
+        .channel_data
+        .pixels
+        .1
 }
 
-fn measure_decode_webp(corpus: &[PathBuf]) {
-    let mut image_rs_total_time = 0;
-    let mut libwebp_total_time = 0;
-    let mut total_pixels = 0;
+/// Times decoding OpenEXR files, and Radiance HDR copies of them encoded in memory. Files in other
+/// formats are converted to 32-bit float OpenEXR first, but only a corpus with values beyond the
+/// 0 to 1 range exercises the formats properly.
+fn measure_decode_hdr(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs_exr = Measurement::new("image-rs OpenEXR");
+    let mut exr_parallel = Measurement::new("exr");
+    let mut exr_single = Measurement::new("exr single-threaded");
+    let mut image_rs_hdr = Measurement::new("image-rs HDR");
 
-    for path in corpus {
-        if let Ok(bytes) = std::fs::read(path) {
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
             let Ok(image) = image::load_from_memory(&bytes) else {
                 continue;
             };
-            if image.width() > 16383 || image.height() > 16383 {
-                continue;
-            }
-            let image: DynamicImage = if image.color().has_alpha() {
-                image.to_rgba8().into()
+            let pixels = image.to_rgb32f();
+            let mut hdr = Vec::new();
+            image::codecs::hdr::HdrEncoder::new(&mut hdr)
+                .encode(
+                    pixels.pixels().copied().collect::<Vec<_>>().as_slice(),
+                    pixels.width() as usize,
+                    pixels.height() as usize,
+                )
+                .unwrap();
+            let image = DynamicImage::ImageRgb32F(pixels);
+            let exr = if image::guess_format(&bytes).ok() == Some(ImageFormat::OpenExr) {
+                bytes
             } else {
-                image.to_rgb8().into()
+                let mut encoded = Vec::new();
+                image
+                    .write_to(&mut Cursor::new(&mut encoded), ImageFormat::OpenExr)
+                    .unwrap();
+                encoded
             };
 
-            let mut encoded = Vec::new();
-            image
-                .write_to(&mut Cursor::new(&mut encoded), ImageFormat::WebP)
-                .unwrap();
+            let start = Timer::start();
+            black_box(image::load_from_memory_with_format(&exr, ImageFormat::OpenExr).unwrap());
+            image_rs_exr.record(path, start.elapsed(), &image, exr.len());
 
-            let start = std::time::Instant::now();
-            black_box(image::load_from_memory(&encoded).unwrap());
-            let elapsed = start.elapsed();
+            let start = Timer::start();
+            black_box(exr_decode(&exr, true));
+            exr_parallel.record(path, start.elapsed(), &image, exr.len());
 
-            let start2 = std::time::Instant::now();
-            black_box(webp::Decoder::new(&encoded).decode().unwrap());
-            let elapsed2 = start2.elapsed();
+            let start = Timer::start();
+            black_box(exr_decode(&exr, false));
+            exr_single.record(path, start.elapsed(), &image, exr.len());
 
-            image_rs_total_time += elapsed.as_nanos();
-            libwebp_total_time += elapsed2.as_nanos();
-            total_pixels += image.width() as u64 * image.height() as u64;
+            let start = Timer::start();
+            black_box(image::load_from_memory_with_format(&hdr, ImageFormat::Hdr).unwrap());
+            image_rs_hdr.record(path, start.elapsed(), &image, hdr.len());
         }
     }
-    let bandwidth = (total_pixels as f64 / (1 << 20) as f64) / (image_rs_total_time as f64 * 1e-9);
-    println!("image-rs WebP: {:>6.1} MP/s", bandwidth);
 
-    let bandwidth = (total_pixels as f64 / (1 << 20) as f64) / (libwebp_total_time as f64 * 1e-9);
-    println!("libwebp:       {:>6.1} MP/s", bandwidth);
+    vec![image_rs_exr, exr_parallel, exr_single, image_rs_hdr]
 }
 
-fn measure_decode_qoi(corpus: &[PathBuf]) {
-    let mut image_rs_total_time = 0;
-    let mut zune_qoi_total_time = 0;
-    let mut total_pixels = 0;
+/// Times encoding and decoding farbfeld, and PPM or PGM depending on whether the image has color,
+/// with image-rs. If netpbm is installed, also times `pamtopnm` reading and rewriting the PNM
+/// file, which includes the cost of starting a process.
+fn measure_uncompressed(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut farbfeld_encode = Measurement::new("image-rs farbfeld encode");
+    let mut farbfeld_decode = Measurement::new("image-rs farbfeld decode");
+    let mut pnm_encode = Measurement::new("image-rs PNM encode");
+    let mut pnm_decode = Measurement::new("image-rs PNM decode");
+    let mut netpbm = Measurement::new("netpbm");
+    let mut netpbm_installed = true;
+
+    let directory = std::env::temp_dir().join(format!("corpus-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let input = directory.join("input.pnm");
 
-    for path in corpus {
-        if let Ok(bytes) = std::fs::read(path) {
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
             let Ok(image) = image::load_from_memory(&bytes) else {
                 continue;
             };
-            let image: DynamicImage = if image.color().has_alpha() {
-                image.to_rgba8().into()
+
+            let rgba16 = DynamicImage::from(image.to_rgba16());
+            let mut farbfeld = Vec::new();
+            let start = Timer::start();
+            rgba16
+                .write_to(&mut Cursor::new(&mut farbfeld), ImageFormat::Farbfeld)
+                .unwrap();
+            farbfeld_encode.record(path, start.elapsed(), &rgba16, farbfeld.len());
+
+            let start = Timer::start();
+            black_box(
+                image::load_from_memory_with_format(&farbfeld, ImageFormat::Farbfeld).unwrap(),
+            );
+            farbfeld_decode.record(path, start.elapsed(), &rgba16, farbfeld.len());
+
+            let image = if image.color().has_color() {
+                DynamicImage::from(image.to_rgb8())
             } else {
-                image.to_rgb8().into()
+                DynamicImage::from(image.to_luma8())
             };
-
-            let mut encoded = Vec::new();
+            let mut pnm = Vec::new();
+            let start = Timer::start();
             image
-                .write_to(&mut Cursor::new(&mut encoded), ImageFormat::Qoi)
+                .write_to(&mut Cursor::new(&mut pnm), ImageFormat::Pnm)
                 .unwrap();
+            pnm_encode.record(path, start.elapsed(), &image, pnm.len());
 
-            let start = std::time::Instant::now();
-            black_box(image::load_from_memory(&encoded).unwrap());
-            let elapsed = start.elapsed();
+            let start = Timer::start();
+            black_box(image::load_from_memory_with_format(&pnm, ImageFormat::Pnm).unwrap());
+            pnm_decode.record(path, start.elapsed(), &image, pnm.len());
 
-            let start2 = std::time::Instant::now();
-            let mut decoder = zune_qoi::QoiDecoder::new_with_options(
-                encoded,
-                zune_qoi::zune_core::options::DecoderOptions::new_fast()
+            if netpbm_installed {
+                std::fs::write(&input, &pnm).unwrap();
+                let start = Timer::start();
+                let output = Command::new("pamtopnm")
+                    .arg(&input)
+                    .stderr(Stdio::null())
+                    .output();
+                let elapsed = start.elapsed();
+
+                match output {
+                    Ok(output) if output.status.success() => {
+                        netpbm.record(path, elapsed, &image, output.stdout.len());
+                    }
+                    Ok(_) => eprintln!("pamtopnm failed on {}", path.display()),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        info!("Skipping netpbm: pamtopnm not installed");
+                        netpbm_installed = false;
+                    }
+                    Err(e) => panic!("Failed to run pamtopnm: {}", e),
+                }
+            }
+        }
+    }
+
+    std::fs::remove_dir_all(&directory).unwrap();
+    let mut results = vec![
+        farbfeld_encode,
+        farbfeld_decode,
+        pnm_encode,
+        pnm_decode,
+        netpbm,
+    ];
+    results.retain(|m| !m.samples.is_empty());
+    results
+}
+
+/// Times doing nothing for each file, both inline and through a dynamically dispatched closure as
+/// most implementations are called, to show how much of every sample is the harness itself.
+fn measure_overhead(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut inline = Measurement::new("no-op");
+    let mut closure = Measurement::new("no-op closure");
+    let no_op: &dyn Fn(&[u8]) = &|bytes| {
+        black_box(bytes);
+    };
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+
+            let start = Timer::start();
+            black_box(&bytes);
+            inline.record(path, start.elapsed(), &image, bytes.len());
+
+            let start = Timer::start();
+            black_box(no_op)(&bytes);
+            closure.record(path, start.elapsed(), &image, bytes.len());
+        }
+    }
+
+    vec![inline, closure]
+}
+
+/// Transforms a decoded image according to the value of an EXIF orientation tag.
+fn apply_orientation(image: DynamicImage, orientation: u8) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Reads the EXIF orientation of a PNG file with zune-png's header parser, or 1 if it has none.
+fn png_orientation(bytes: &[u8]) -> u8 {
+    let mut decoder = zune_png::PngDecoder::new_with_options(
+        Cursor::new(bytes),
+        zune_png::zune_core::options::DecoderOptions::new_fast()
+            .set_max_width(usize::MAX)
+            .set_max_height(usize::MAX),
+    );
+    if decoder.decode_headers().is_err() {
+        return 1;
+    }
+    decoder
+        .info()
+        .and_then(|info| exif::orientation(info.exif.as_deref()?))
+        .unwrap_or(1)
+}
+
+/// Times decoding each file and applying the orientation recorded in its EXIF data, as photo
+/// pipelines do before showing or resizing an image. Reading the orientation is part of the timed
+/// region, and files without one are only decoded. image 0.25.1 can't report EXIF, so the image-rs
+/// pipeline reads it with zune-png's header parser. Both pipelines rotate and flip with image-rs.
+fn measure_decode_orientation(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut image_rs = Measurement::new("image-rs");
+    let mut zune_png = Measurement::new("zune-png");
+    let mut oriented = 0;
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(bytes) = archive::read(path) {
+            let Ok(image) = image::load_from_memory(&bytes) else {
+                continue;
+            };
+
+            let start = Timer::start();
+            let orientation = png_orientation(&bytes);
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            black_box(apply_orientation(decoded, orientation));
+            image_rs.record(path, start.elapsed(), &image, bytes.len());
+
+            let start = Timer::start();
+            let mut decoder = zune_png::PngDecoder::new_with_options(
+                Cursor::new(&bytes),
+                zune_png::zune_core::options::DecoderOptions::new_fast()
                     .set_max_width(usize::MAX)
                     .set_max_height(usize::MAX),
             );
-            black_box(decoder.decode().unwrap());
-            let elapsed2 = start2.elapsed();
+            let pixels = decoder.decode().unwrap();
+            let orientation = decoder
+                .info()
+                .and_then(|info| exif::orientation(info.exif.as_deref()?))
+                .unwrap_or(1);
+            let channels = decoder.colorspace().unwrap().num_components();
+            let decoded =
+                normalize::from_zune(pixels, image.width(), image.height(), channels).unwrap();
+            black_box(apply_orientation(decoded, orientation));
+            zune_png.record(path, start.elapsed(), &image, bytes.len());
+
+            if orientation != 1 {
+                oriented += 1;
+            }
+        }
+    }
+
+    info!(
+        "{} of {} files have an EXIF orientation to apply",
+        oriented,
+        image_rs.samples.len()
+    );
+    vec![image_rs, zune_png]
+}
+
+/// Compares constructing encoder state for every image, as a command line tool would, against
+/// constructing it once and reusing it for the whole corpus, as a long-running server would.
+fn measure_encode_reuse(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let thread_pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+    let image_rs_png = |buffer: &mut Cursor<Vec<u8>>, image: &DynamicImage| {
+        image.write_to(buffer, ImageFormat::Png).unwrap();
+    };
+
+    vec![
+        measure_encode("mtpng new pool", corpus, budget, false, |buffer, image| {
+            let thread_pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+            mtpng_write(buffer, image, Some(&thread_pool), &[]);
+        }),
+        measure_encode(
+            "mtpng reused pool",
+            corpus,
+            budget,
+            true,
+            |buffer, image| {
+                mtpng_write(buffer, image, Some(&thread_pool), &[]);
+            },
+        ),
+        measure_encode("zune-png new buffer", corpus, budget, false, zune_png_write),
+        measure_encode(
+            "zune-png reused buffer",
+            corpus,
+            budget,
+            true,
+            zune_png_write,
+        ),
+        measure_encode(
+            "image-rs PNG new buffer",
+            corpus,
+            budget,
+            false,
+            image_rs_png,
+        ),
+        measure_encode(
+            "image-rs PNG reused buffer",
+            corpus,
+            budget,
+            true,
+            image_rs_png,
+        ),
+    ]
+}
+
+/// Rows of the padded source buffers are rounded up to a multiple of this many bytes, and then
+/// padded by as many again, like a framebuffer with a generous alignment.
+const ROW_ALIGNMENT: usize = 64;
+
+/// Encodes packed 8-bit pixels as PNG with image-rs.
+fn image_rs_png_write(buffer: &mut Cursor<Vec<u8>>, pixels: &[u8], image: &DynamicImage) {
+    image::codecs::png::PngEncoder::new(buffer)
+        .write_image(pixels, image.width(), image.height(), image.color().into())
+        .unwrap();
+}
+
+/// Times encoding with image-rs from pixel buffers laid out the way GUI toolkits and capture APIs
+/// often provide them, including converting them to the packed RGB(A) the encoder takes, compared
+/// with encoding from packed pixels directly.
+fn measure_encode_layout(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut packed = Measurement::new("image-rs PNG packed");
+    let mut padded = Measurement::new("image-rs PNG from padded rows");
+    let mut bgr = Measurement::new("image-rs PNG from BGR");
+    let mut planar = Measurement::new("image-rs PNG from planar");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(image) = archive::open_image(path) {
+            let image = normalize::to_rgb8_or_rgba8(&image);
+            let pixels = image.as_bytes();
+            let channels = image.color().channel_count() as usize;
+            let row_bytes = image.width() as usize * channels;
+
+            let stride = row_bytes.next_multiple_of(ROW_ALIGNMENT) + ROW_ALIGNMENT;
+            let mut padded_source = vec![0; stride * image.height() as usize];
+            for (dst, src) in padded_source
+                .chunks_exact_mut(stride)
+                .zip(pixels.chunks_exact(row_bytes))
+            {
+                dst[..row_bytes].copy_from_slice(src);
+            }
+            let mut bgr_source = pixels.to_vec();
+            for pixel in bgr_source.chunks_exact_mut(channels) {
+                pixel.swap(0, 2);
+            }
+            let planar_source: Vec<Vec<u8>> = (0..channels)
+                .map(|c| pixels.iter().skip(c).step_by(channels).copied().collect())
+                .collect();
+
+            let mut buffer = Cursor::new(Vec::new());
+            let start = Timer::start();
+            image_rs_png_write(&mut buffer, pixels, &image);
+            packed.record(path, start.elapsed(), &image, buffer.get_ref().len());
+
+            let mut buffer = Cursor::new(Vec::new());
+            let start = Timer::start();
+            let mut converted = Vec::with_capacity(pixels.len());
+            for row in padded_source.chunks_exact(stride) {
+                converted.extend_from_slice(&row[..row_bytes]);
+            }
+            image_rs_png_write(&mut buffer, &converted, &image);
+            padded.record(path, start.elapsed(), &image, buffer.get_ref().len());
+
+            let mut buffer = Cursor::new(Vec::new());
+            let start = Timer::start();
+            let mut converted = bgr_source.clone();
+            for pixel in converted.chunks_exact_mut(channels) {
+                pixel.swap(0, 2);
+            }
+            image_rs_png_write(&mut buffer, &converted, &image);
+            bgr.record(path, start.elapsed(), &image, buffer.get_ref().len());
+
+            let mut buffer = Cursor::new(Vec::new());
+            let start = Timer::start();
+            let mut converted = vec![0; pixels.len()];
+            for (c, plane) in planar_source.iter().enumerate() {
+                for (dst, &src) in converted.iter_mut().skip(c).step_by(channels).zip(plane) {
+                    *dst = src;
+                }
+            }
+            image_rs_png_write(&mut buffer, &converted, &image);
+            planar.record(path, start.elapsed(), &image, buffer.get_ref().len());
+        }
+    }
+
+    vec![packed, padded, bgr, planar]
+}
+
+/// A writer that discards everything written to it, only counting the bytes
+struct CountingWriter(usize);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes an 8-bit RGB or RGBA image to any writer in some format.
+type SinkEncodeFn = fn(&mut dyn Write, &DynamicImage);
+
+/// Encoders that can write to any `Write` implementation, rather than only to a `Vec`
+const SINK_ENCODERS: [(&str, SinkEncodeFn); 4] = [
+    ("image-rs PNG", |writer, image| {
+        let encoder = image::codecs::png::PngEncoder::new(writer);
+        image.write_with_encoder(encoder).unwrap()
+    }),
+    ("image-rs QOI", |writer, image| {
+        let encoder = image::codecs::qoi::QoiEncoder::new(writer);
+        image.write_with_encoder(encoder).unwrap()
+    }),
+    ("image-rs WebP", |writer, image| {
+        let encoder = image::codecs::webp::WebPEncoder::new_lossless(writer);
+        image.write_with_encoder(encoder).unwrap()
+    }),
+    ("mtpng", |writer, image| {
+        mtpng_write(writer, image, None, &[])
+    }),
+];
+
+/// Times each encoder writing to a new `Vec` and to a sink that only counts bytes, which shows the
+/// cost of growing the output buffer and compares fairly with C encoders that write through
+/// callbacks.
+fn measure_encode_sink(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let mut results: Vec<(Measurement, Measurement)> = SINK_ENCODERS
+        .iter()
+        .map(|(name, _)| {
+            (
+                Measurement::new(format!("{} to Vec", name)),
+                Measurement::new(format!("{} to counting sink", name)),
+            )
+        })
+        .collect();
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(image) = archive::open_image(path) {
+            if image.width() > 16383 || image.height() > 16383 {
+                continue;
+            }
+            let image = normalize::to_rgb8_or_rgba8(&image);
+
+            for ((_, write), (to_vec, to_sink)) in SINK_ENCODERS.iter().zip(&mut results) {
+                let mut output = Vec::new();
+                let start = Timer::start();
+                write(&mut output, &image);
+                to_vec.record(path, start.elapsed(), &image, output.len());
+
+                let mut sink = CountingWriter(0);
+                let start = Timer::start();
+                write(&mut sink, &image);
+                to_sink.record(path, start.elapsed(), &image, sink.0);
+            }
+        }
+    }
+
+    results
+        .into_iter()
+        .flat_map(|(to_vec, to_sink)| [to_vec, to_sink])
+        .collect()
+}
 
-            image_rs_total_time += elapsed.as_nanos();
-            zune_qoi_total_time += elapsed2.as_nanos();
-            total_pixels += image.width() as u64 * image.height() as u64;
+/// The type and data of a PNG chunk
+type Chunk = ([u8; 4], Vec<u8>);
+
+/// A minimal little-endian EXIF block with the camera and software tags a photo would have.
+fn sample_exif() -> Vec<u8> {
+    let fields = [
+        (0x010f_u16, "Canon"),
+        (0x0110, "Canon EOS R5"),
+        (0x0131, "Adobe Photoshop Lightroom Classic 13.1 (Windows)"),
+        (0x0132, "2024:01:01 12:00:00"),
+    ];
+    let ifd_len = 2 + 12 * fields.len() + 4;
+
+    let mut exif = b"II*\0".to_vec();
+    exif.extend(8u32.to_le_bytes());
+    exif.extend((fields.len() as u16).to_le_bytes());
+    let mut values = Vec::new();
+    for (tag, value) in fields {
+        let offset = 8 + ifd_len + values.len();
+        exif.extend(tag.to_le_bytes());
+        exif.extend(2u16.to_le_bytes());
+        exif.extend((value.len() as u32 + 1).to_le_bytes());
+        exif.extend((offset as u32).to_le_bytes());
+        values.extend(value.as_bytes());
+        values.push(0);
+    }
+    exif.extend(0u32.to_le_bytes());
+    exif.extend(values);
+    exif
+}
+
+/// The chunks an asset pipeline that preserves metadata would write: an ICC profile, EXIF, and
+/// a few text chunks. The profile is compressed here, so this is called inside the timed region.
+fn metadata_chunks(icc: &[u8], exif: &[u8]) -> Vec<Chunk> {
+    let mut iccp = b"Display P3\0\0".to_vec();
+    iccp.extend(fdeflate::compress_to_vec(icc));
+
+    let mut chunks = vec![(*b"iCCP", iccp), (*b"eXIf", exif.to_vec())];
+    for (keyword, text) in [
+        ("Title", "Untitled"),
+        ("Author", "corpus-bench"),
+        ("Copyright", "Copyright 2024, all rights reserved"),
+        ("Software", "corpus-bench"),
+    ] {
+        chunks.push((
+            *b"tEXt",
+            [keyword.as_bytes(), b"\0", text.as_bytes()].concat(),
+        ));
+    }
+    chunks
+}
+
+/// Writes `image` as a PNG with the png crate, with `chunks` written between the header and
+/// image data.
+fn image_png_write(buffer: &mut Vec<u8>, image: &DynamicImage, chunks: &[Chunk]) {
+    let mut encoder = png::Encoder::new(buffer, image.width(), image.height());
+    encoder.set_color(if image.color().has_alpha() {
+        png::ColorType::Rgba
+    } else {
+        png::ColorType::Rgb
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    for (name, data) in chunks {
+        writer
+            .write_chunk(png::chunk::ChunkType(*name), data)
+            .unwrap();
+    }
+    writer.write_image_data(image.as_bytes()).unwrap();
+}
+
+/// Times image-png and mtpng encoding bare images and the same images with metadata attached.
+fn measure_encode_metadata(corpus: &[PathBuf], budget: Option<Duration>) -> Vec<Measurement> {
+    let icc = moxcms::ColorProfile::new_display_p3().encode().unwrap();
+    let exif = sample_exif();
+
+    let mut image_png_bare = Measurement::new("image-png bare");
+    let mut image_png_metadata = Measurement::new("image-png with metadata");
+    let mut mtpng_bare = Measurement::new("mtpng bare");
+    let mut mtpng_metadata = Measurement::new("mtpng with metadata");
+
+    for path in budgeted(corpus, budget) {
+        if let Ok(image) = archive::open_image(path) {
+            if image.width() > 16383 || image.height() > 16383 {
+                continue;
+            }
+            let image = normalize::to_rgb8_or_rgba8(&image);
+
+            let mut output = Vec::new();
+            let start = Timer::start();
+            image_png_write(&mut output, &image, &[]);
+            image_png_bare.record(path, start.elapsed(), &image, output.len());
+
+            let mut output = Vec::new();
+            let start = Timer::start();
+            image_png_write(&mut output, &image, &metadata_chunks(&icc, &exif));
+            image_png_metadata.record(path, start.elapsed(), &image, output.len());
+
+            let mut output = Vec::new();
+            let start = Timer::start();
+            mtpng_write(&mut output, &image, None, &[]);
+            mtpng_bare.record(path, start.elapsed(), &image, output.len());
+
+            let mut output = Vec::new();
+            let start = Timer::start();
+            mtpng_write(&mut output, &image, None, &metadata_chunks(&icc, &exif));
+            mtpng_metadata.record(path, start.elapsed(), &image, output.len());
         }
     }
-    let bandwidth = (total_pixels as f64 / (1 << 20) as f64) / (image_rs_total_time as f64 * 1e-9);
-    println!("image-rs QOI:  {:>6.1} MP/s", bandwidth);
 
-    let bandwidth = (total_pixels as f64 / (1 << 20) as f64) / (zune_qoi_total_time as f64 * 1e-9);
-    println!("zune-qoi:      {:>6.1} MP/s", bandwidth);
+    vec![
+        image_png_bare,
+        image_png_metadata,
+        mtpng_bare,
+        mtpng_metadata,
+    ]
+}
+
+/// Times encoders `a` and `b` back-to-back on every file, `rounds` times in random order, and
+/// returns the median over the rounds of how many times longer `b` took than `a` on each file.
+/// Slow drift in the machine's speed affects both halves of a pair about equally, so it mostly
+/// cancels out of the ratios.
+fn measure_paired(
+    corpus: &[PathBuf],
+    budget: Option<Duration>,
+    (a, b): (EncodeFn, EncodeFn),
+    rounds: u32,
+) -> Vec<f64> {
+    let mut ratios = Vec::new();
+    for path in budgeted(corpus, budget) {
+        if let Ok(image) = archive::open_image(path) {
+            if image.width() > 16383 || image.height() > 16383 {
+                continue;
+            }
+            let image = normalize::to_rgb8_or_rgba8(&image);
+
+            let time = |write: EncodeFn| {
+                let mut buffer = Cursor::new(Vec::new());
+                let start = Timer::start();
+                write(&mut buffer, &image);
+                start.elapsed().duration.as_nanos() as f64
+            };
+            let mut round_ratios: Vec<f64> = (0..rounds)
+                .map(|_| {
+                    if rand::random() {
+                        let a_nanos = time(a);
+                        time(b) / a_nanos
+                    } else {
+                        let b_nanos = time(b);
+                        b_nanos / time(a)
+                    }
+                })
+                .collect();
+            round_ratios.sort_by(f64::total_cmp);
+            ratios.push(stats::percentile(&round_ratios, 0.5));
+        }
+    }
+    ratios
 }
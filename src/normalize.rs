@@ -0,0 +1,50 @@
+//! Converting images into common formats, so that the output of different decoders can be
+//! compared and every encoder can be given the same input.
+
+use image::{DynamicImage, ImageBuffer, Luma, LumaA, Rgb, Rgba};
+use zune_png::zune_core::result::DecodingResult;
+
+/// Wraps the pixels zune-png decoded into a `DynamicImage` without copying them, or returns
+/// `None` if they don't fit the dimensions and channel count.
+pub fn from_zune(
+    pixels: DecodingResult,
+    width: u32,
+    height: u32,
+    channels: usize,
+) -> Option<DynamicImage> {
+    Some(match (pixels, channels) {
+        (DecodingResult::U8(p), 1) => {
+            ImageBuffer::<Luma<u8>, _>::from_raw(width, height, p)?.into()
+        }
+        (DecodingResult::U8(p), 2) => {
+            ImageBuffer::<LumaA<u8>, _>::from_raw(width, height, p)?.into()
+        }
+        (DecodingResult::U8(p), 3) => ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, p)?.into(),
+        (DecodingResult::U8(p), 4) => {
+            ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, p)?.into()
+        }
+        (DecodingResult::U16(p), 1) => {
+            ImageBuffer::<Luma<u16>, _>::from_raw(width, height, p)?.into()
+        }
+        (DecodingResult::U16(p), 2) => {
+            ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, p)?.into()
+        }
+        (DecodingResult::U16(p), 3) => {
+            ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, p)?.into()
+        }
+        (DecodingResult::U16(p), 4) => {
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, p)?.into()
+        }
+        _ => return None,
+    })
+}
+
+/// Converts `image` to 8-bit RGBA if it has an alpha channel and to 8-bit RGB otherwise, which
+/// every encoder accepts.
+pub fn to_rgb8_or_rgba8(image: &DynamicImage) -> DynamicImage {
+    if image.color().has_alpha() {
+        image.to_rgba8().into()
+    } else {
+        image.to_rgb8().into()
+    }
+}
@@ -0,0 +1,191 @@
+//! Pinning the size of every encoder's output for every corpus file, so that later runs can
+//! report exactly which files a change to an encoder made bigger or smaller. Unlike timings,
+//! sizes are deterministic, so any change is a real one.
+//!
+//! Each line of a pin file holds the encoder, the size of its output in bytes and the corpus file,
+//! separated by tabs.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::results::Measurement;
+
+/// Maximum number of changed files to list per encoder.
+const MAX_CHANGES_LISTED: usize = 20;
+
+/// Writes the output size of every sample in `results` to `path`.
+pub fn write(path: &Path, results: &[Measurement]) {
+    let mut contents = String::new();
+    for measurement in results {
+        for sample in &measurement.samples {
+            contents += &format!(
+                "{}\t{}\t{}\n",
+                measurement.name,
+                sample.compressed_bytes,
+                sample.path.display()
+            );
+        }
+    }
+    std::fs::write(path, contents).unwrap();
+    println!("Pinned output sizes in {}", path.display());
+}
+
+/// Compares the output sizes in `results` with those pinned in `path`, printing every file whose
+/// size changed and every pin that no longer has a sample, including those of encoders that are
+/// gone. Returns whether there were any such changes.
+pub fn compare(path: &Path, results: &[Measurement]) -> bool {
+    let contents = std::fs::read_to_string(path).unwrap();
+    // Pinned sizes by encoder and then file; entries are removed as samples are matched to them.
+    let mut pinned: HashMap<&str, HashMap<&str, u64>> = HashMap::new();
+    for line in contents.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [name, bytes, file] = fields[..] else {
+            panic!("Malformed line in {}: {:?}", path.display(), line);
+        };
+        pinned
+            .entry(name)
+            .or_default()
+            .insert(file, bytes.parse().unwrap());
+    }
+
+    let mut any_changed = false;
+    for measurement in results {
+        let mut encoder_pins = pinned.remove(measurement.name.as_str()).unwrap_or_default();
+        let mut changes = Vec::new();
+        let (mut before_total, mut after_total, mut unpinned) = (0, 0, 0);
+        for sample in &measurement.samples {
+            let file = sample.path.to_string_lossy();
+            let Some(before) = encoder_pins.remove(&*file) else {
+                unpinned += 1;
+                continue;
+            };
+            before_total += before;
+            after_total += sample.compressed_bytes;
+            if before != sample.compressed_bytes {
+                changes.push((before, sample.compressed_bytes, &sample.path));
+            }
+        }
+
+        let mut missing: Vec<&str> = encoder_pins.into_keys().collect();
+        missing.sort_unstable();
+
+        if changes.is_empty() {
+            println!("{}: no output sizes changed", measurement.name);
+        } else {
+            any_changed = true;
+            println!(
+                "{}: {} files changed size, {:+.3}% overall",
+                measurement.name,
+                changes.len(),
+                (after_total as f64 / before_total as f64 - 1.0) * 100.0
+            );
+            // Largest relative changes first, in either direction.
+            changes.sort_by(|a, b| {
+                let change = |(before, after, _): &(u64, u64, _)| {
+                    (*after as f64 / *before as f64).ln().abs()
+                };
+                change(b).total_cmp(&change(a))
+            });
+            for (before, after, path) in changes.iter().take(MAX_CHANGES_LISTED) {
+                println!(
+                    "  {:>+8.3}%  {} -> {} bytes  {}",
+                    (*after as f64 / *before as f64 - 1.0) * 100.0,
+                    before,
+                    after,
+                    path.display()
+                );
+            }
+        }
+        if !missing.is_empty() {
+            any_changed = true;
+            println!("  {} pinned files have no sample:", missing.len());
+            for file in missing.iter().take(MAX_CHANGES_LISTED) {
+                println!("    {}", file);
+            }
+        }
+        if unpinned > 0 {
+            println!("  {} files weren't pinned", unpinned);
+        }
+    }
+
+    // Whatever is left was pinned for encoders that no longer ran.
+    let mut vanished: Vec<(&str, usize)> = pinned
+        .into_iter()
+        .map(|(name, files)| (name, files.len()))
+        .collect();
+    vanished.sort_unstable();
+    for (name, files) in vanished {
+        any_changed = true;
+        println!(
+            "{}: pinned for {} files but no longer measured",
+            name, files
+        );
+    }
+    any_changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{results::Sample, testing::TempFile};
+
+    /// An encoder's output sizes for each file.
+    fn encoded(name: &str, sizes: &[(&str, u64)]) -> Measurement {
+        let samples = sizes
+            .iter()
+            .map(|&(file, size)| Sample::for_test(file, 1, 4096, size));
+        Measurement::for_test(name, samples)
+    }
+
+    /// Pins `pinned`, then returns whether comparing `results` with the pins finds a change.
+    fn changed(name: &str, pinned: &[Measurement], results: &[Measurement]) -> bool {
+        let pins = TempFile::new(name);
+        write(pins.path(), pinned);
+        compare(pins.path(), results)
+    }
+
+    #[test]
+    fn identical_sizes_are_unchanged() {
+        let results = [
+            encoded("zune-png", &[("corpus/a.png", 100), ("corpus/b.png", 200)]),
+            encoded("mtpng", &[("corpus/a.png", 120)]),
+        ];
+        assert!(!changed("identical.tsv", &results, &results));
+        // Files that weren't pinned are listed but aren't changes.
+        let more = [
+            encoded("zune-png", &[("corpus/a.png", 100), ("corpus/b.png", 200)]),
+            encoded("mtpng", &[("corpus/a.png", 120), ("corpus/new.png", 5)]),
+        ];
+        assert!(!changed("unpinned.tsv", &results, &more));
+    }
+
+    #[test]
+    fn size_changes_are_found() {
+        let pinned = [encoded(
+            "zune-png",
+            &[("corpus/a.png", 100), ("corpus/b.png", 200)],
+        )];
+        let results = [encoded(
+            "zune-png",
+            &[("corpus/a.png", 100), ("corpus/b.png", 199)],
+        )];
+        assert!(changed("resized.tsv", &pinned, &results));
+    }
+
+    #[test]
+    fn pins_without_a_sample_are_changes() {
+        let pinned = [
+            encoded("zune-png", &[("corpus/a.png", 100), ("corpus/b.png", 200)]),
+            encoded("mtpng", &[("corpus/a.png", 120)]),
+        ];
+        let missing_file = [
+            encoded("zune-png", &[("corpus/a.png", 100)]),
+            encoded("mtpng", &[("corpus/a.png", 120)]),
+        ];
+        assert!(changed("missing-file.tsv", &pinned, &missing_file));
+        let missing_encoder = [encoded(
+            "zune-png",
+            &[("corpus/a.png", 100), ("corpus/b.png", 200)],
+        )];
+        assert!(changed("missing-encoder.tsv", &pinned, &missing_encoder));
+    }
+}
@@ -0,0 +1,1683 @@
+//! Per-file measurements and the summaries printed from them.
+
+use std::{
+    collections::HashMap,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use image::DynamicImage;
+
+use crate::stats;
+
+/// The time taken by one implementation to process one corpus file
+pub struct Sample {
+    /// The corpus file the sample was taken from
+    pub path: PathBuf,
+    /// Time taken in nanoseconds
+    pub nanos: u128,
+    /// Number of pixels in the image
+    pub pixels: u64,
+    /// Size of the uncompressed pixel data in bytes
+    pub raw_bytes: u64,
+    /// Size of the compressed data that was produced or consumed in bytes
+    pub compressed_bytes: u64,
+    /// Allocations made while processing the file, if they were counted
+    pub allocations: Option<AllocationStats>,
+    /// How far the resident set size grew above its size before processing the file, if known
+    pub peak_rss_growth: Option<u64>,
+    /// The buffer the implementation returned its output in, if it is known
+    pub output_buffer: Option<OutputBuffer>,
+    /// How the implementation handled the input, for inputs it may reject
+    pub outcome: Option<Outcome>,
+}
+
+/// How a decoder handled an input that it may reject
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Accepted,
+    Rejected,
+    /// The decoder panicked
+    Crashed,
+}
+
+/// How much memory was reserved for an output buffer compared to how much of it was used
+#[derive(Clone, Copy, Debug)]
+pub struct OutputBuffer {
+    pub capacity_bytes: u64,
+    pub len_bytes: u64,
+}
+
+impl OutputBuffer {
+    pub fn of<T>(buffer: &Vec<T>) -> Self {
+        let size = std::mem::size_of::<T>() as u64;
+        Self {
+            capacity_bytes: buffer.capacity() as u64 * size,
+            len_bytes: buffer.len() as u64 * size,
+        }
+    }
+}
+
+/// Heap allocations made by the Rust code in a timed region
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(feature = "count-allocations"), allow(dead_code))]
+pub struct AllocationStats {
+    pub allocations: u64,
+    pub reallocations: u64,
+    /// Highest number of bytes allocated at once, beyond those live before the region started
+    pub peak_bytes: u64,
+}
+
+/// Whether timers only run the code they wrap, as in a dry run, without evicting caches, reading
+/// memory usage or reporting samples.
+static UNTIMED: AtomicBool = AtomicBool::new(false);
+
+/// When the last timer stopped or the current pass over the corpus started. The untimed wall clock
+/// time from then until the next timer starts is charged to that timer's implementation.
+static LAST_MARK: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Total time spent in timed regions since the current pass over the corpus started.
+static PASS_TIMED: Mutex<Duration> = Mutex::new(Duration::ZERO);
+
+/// Marks the start of a pass over the corpus, so that the time before its first timer starts is
+/// charged to that timer's implementation rather than to whatever ran before.
+pub fn start_pass() {
+    *LAST_MARK.lock().unwrap() = Some(Instant::now());
+    *PASS_TIMED.lock().unwrap() = Duration::ZERO;
+}
+
+/// Time spent in timed regions since the current pass over the corpus started, which leaves out
+/// reading, decoding and converting the inputs.
+pub fn timed_in_pass() -> Duration {
+    *PASS_TIMED.lock().unwrap()
+}
+
+/// Turns the measurement around timed regions off, so that implementations can be checked without
+/// being benchmarked.
+pub fn set_untimed(untimed: bool) {
+    UNTIMED.store(untimed, Ordering::Relaxed);
+}
+
+/// Whether the measurement around timed regions is turned off, as in a dry run.
+pub fn untimed() -> bool {
+    UNTIMED.load(Ordering::Relaxed)
+}
+
+/// Measures the time taken by a region of code, along with its peak resident set size and, when
+/// the `count-allocations` feature is enabled, its allocations. Neither of those is included in
+/// the time.
+pub struct Timer {
+    start: Instant,
+    setup: Duration,
+    rss: Option<u64>,
+    #[cfg(feature = "count-allocations")]
+    allocations: crate::alloc::Snapshot,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        let setup = LAST_MARK
+            .lock()
+            .unwrap()
+            .map_or(Duration::ZERO, |mark| mark.elapsed());
+        let untimed = UNTIMED.load(Ordering::Relaxed);
+        if !untimed {
+            crate::cache::evict();
+        }
+        // Reading procfs allocates, so the peak is reset before the allocation snapshot is taken
+        // and read after the allocations are counted, keeping the harness's reads out of them.
+        let rss = if untimed {
+            None
+        } else {
+            crate::rss::reset_peak()
+        };
+        Self {
+            #[cfg(feature = "count-allocations")]
+            allocations: crate::alloc::Snapshot::take(),
+            rss,
+            setup,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Timing {
+        let duration = self.start.elapsed();
+        #[cfg(feature = "count-allocations")]
+        let allocations = Some(self.allocations.since());
+        #[cfg(not(feature = "count-allocations"))]
+        let allocations = None;
+        let peak_rss_growth = self
+            .rss
+            .zip(crate::rss::peak())
+            .map(|(start, peak)| peak.saturating_sub(start));
+        *LAST_MARK.lock().unwrap() = Some(Instant::now());
+        *PASS_TIMED.lock().unwrap() += duration;
+        Timing {
+            duration,
+            setup: self.setup,
+            peak_rss_growth,
+            allocations,
+        }
+    }
+}
+
+/// The result of a `Timer`
+pub struct Timing {
+    pub duration: Duration,
+    /// Untimed wall clock time between the previous timer stopping and this one starting
+    pub setup: Duration,
+    pub allocations: Option<AllocationStats>,
+    pub peak_rss_growth: Option<u64>,
+}
+
+impl Sample {
+    pub fn throughput(&self, unit: Unit) -> f64 {
+        unit.of(self.nanos, self.pixels, self.raw_bytes, 1)
+    }
+}
+
+/// All samples taken for one implementation
+pub struct Measurement {
+    pub name: String,
+    pub samples: Vec<Sample>,
+    /// Wall clock time in nanoseconds charged to the implementation: the time of each sample plus
+    /// the untimed work before it since the previous timer stopped, such as preparing its input.
+    /// Work shared by several implementations is charged to the first one timed after it.
+    pub wall_nanos: u128,
+}
+
+impl Measurement {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            samples: Vec::new(),
+            wall_nanos: 0,
+        }
+    }
+
+    /// Creates one measurement for every combination of a row and a column, named by `name`. They
+    /// are in row-major order, so the one for `rows[i]` and `columns[j]` is at
+    /// `i * columns.len() + j`.
+    pub fn grid<R, C>(rows: &[R], columns: &[C], name: impl Fn(&R, &C) -> String) -> Vec<Self> {
+        rows.iter()
+            .flat_map(|row| columns.iter().map(|column| Self::new(name(row, column))))
+            .collect()
+    }
+
+    /// Records the time taken to process `image`, which was read from `path`, returning the new
+    /// sample so that optional details can be filled in.
+    pub fn record(
+        &mut self,
+        path: &Path,
+        elapsed: Timing,
+        image: &DynamicImage,
+        compressed_bytes: usize,
+    ) -> &mut Sample {
+        self.wall_nanos += (elapsed.setup + elapsed.duration).as_nanos();
+        let untimed = UNTIMED.load(Ordering::Relaxed);
+        if crate::verbosity() == crate::Verbosity::Verbose && !untimed {
+            println!(
+                "{}: {} {:.3} ms",
+                self.name,
+                path.display(),
+                elapsed.duration.as_secs_f64() * 1e3
+            );
+        }
+        self.samples.push(Sample {
+            path: path.to_owned(),
+            nanos: elapsed.duration.as_nanos(),
+            pixels: image.width() as u64 * image.height() as u64,
+            raw_bytes: image.as_bytes().len() as u64,
+            compressed_bytes: compressed_bytes as u64,
+            allocations: elapsed.allocations,
+            peak_rss_growth: elapsed.peak_rss_growth,
+            output_buffer: None,
+            outcome: None,
+        });
+        let sample = self.samples.last_mut().unwrap();
+        if !untimed {
+            crate::events::sample(
+                &self.name,
+                path,
+                sample.nanos,
+                sample.pixels,
+                sample.compressed_bytes,
+            );
+        }
+        sample
+    }
+
+    /// Each file's time, for matching samples up with another measurement of the same files.
+    pub fn nanos_by_path(&self) -> HashMap<&Path, u128> {
+        self.samples
+            .iter()
+            .map(|s| (s.path.as_path(), s.nanos))
+            .collect()
+    }
+
+    pub fn total_nanos(&self) -> u128 {
+        self.samples.iter().map(|s| s.nanos).sum()
+    }
+
+    pub fn throughput(&self, unit: Unit) -> f64 {
+        unit.of(
+            self.total_nanos(),
+            self.samples.iter().map(|s| s.pixels).sum(),
+            self.samples.iter().map(|s| s.raw_bytes).sum(),
+            self.samples.len() as u64,
+        )
+    }
+
+    /// Samples whose throughput is a positive finite number. A file that took less time than the
+    /// timer can resolve has an infinite throughput, which would swamp every mean.
+    fn finite_samples(&self, unit: Unit) -> impl Iterator<Item = &Sample> + Clone {
+        self.samples.iter().filter(move |s| {
+            let throughput = s.throughput(unit);
+            throughput.is_finite() && throughput > 0.0
+        })
+    }
+
+    /// Arithmetic, geometric and harmonic means of the per-file throughputs, or `None` if no file
+    /// has a finite throughput.
+    pub fn mean_throughputs(&self, unit: Unit) -> Option<(f64, f64, f64)> {
+        let throughputs = self.finite_samples(unit).map(|s| s.throughput(unit));
+        let n = throughputs.clone().count() as f64;
+        if n == 0.0 {
+            return None;
+        }
+        let arithmetic = throughputs.clone().sum::<f64>() / n;
+        let geometric = (throughputs.clone().map(f64::ln).sum::<f64>() / n).exp();
+        let harmonic = n / throughputs.map(|t| 1.0 / t).sum::<f64>();
+        Some((arithmetic, geometric, harmonic))
+    }
+
+    /// Standard deviation of the per-file throughputs divided by their mean, over the files with a
+    /// finite throughput.
+    pub fn coefficient_of_variation(&self, unit: Unit) -> f64 {
+        let throughputs: Vec<f64> = self
+            .finite_samples(unit)
+            .map(|s| s.throughput(unit))
+            .collect();
+        stats::coefficient_of_variation(&throughputs)
+    }
+
+    /// Per-file times in milliseconds, sorted from fastest to slowest.
+    pub fn latencies_ms(&self) -> Vec<f64> {
+        let mut millis: Vec<f64> = self.samples.iter().map(|s| s.nanos as f64 * 1e-6).collect();
+        millis.sort_by(f64::total_cmp);
+        millis
+    }
+
+    /// Per-file compressed sizes as a fraction of the uncompressed size, sorted from smallest.
+    pub fn compression_ratios(&self) -> Vec<f64> {
+        let mut ratios: Vec<f64> = self
+            .samples
+            .iter()
+            .map(|s| s.compressed_bytes as f64 / s.raw_bytes as f64)
+            .collect();
+        ratios.sort_by(f64::total_cmp);
+        ratios
+    }
+
+    pub fn compression_ratio(&self) -> f64 {
+        let compressed: u64 = self.samples.iter().map(|s| s.compressed_bytes).sum();
+        let raw: u64 = self.samples.iter().map(|s| s.raw_bytes).sum();
+        compressed as f64 / raw as f64
+    }
+}
+
+/// Run-to-run coefficient of variation above which a file's timing is reported as unstable.
+const UNSTABLE_THRESHOLD: f64 = 0.10;
+
+/// How many times an even share of the run an implementation must take before it is suggested for
+/// leaving out of quick runs.
+const SLOW_SHARE_FACTOR: f64 = 2.0;
+
+/// Maximum number of unstable files to list.
+const MAX_UNSTABLE_LISTED: usize = 10;
+
+/// The `i`th implementation's samples from the first run of the files it measured in every run,
+/// each with the file's time in every run. Files are matched by path, since runs can visit them in
+/// different orders and a time budget can cut some runs short.
+fn matched_samples(runs: &[Vec<Measurement>], i: usize) -> Vec<(&Sample, Vec<u128>)> {
+    let later: Vec<HashMap<&Path, u128>> = runs[1..].iter().map(|r| r[i].nanos_by_path()).collect();
+    runs[0][i]
+        .samples
+        .iter()
+        .filter_map(|sample| {
+            let mut nanos = vec![sample.nanos];
+            for run in &later {
+                nanos.push(*run.get(sample.path.as_path())?);
+            }
+            Some((sample, nanos))
+        })
+        .collect()
+}
+
+/// Combines repeated runs of the same benchmark into one measurement per implementation, using the
+/// median time across runs for each file measured in every run and the total wall clock time across all runs.
+pub fn combine_runs(runs: &[Vec<Measurement>]) -> Vec<Measurement> {
+    let first = &runs[0];
+    first
+        .iter()
+        .enumerate()
+        .map(|(i, measurement)| Measurement {
+            name: measurement.name.clone(),
+            samples: matched_samples(runs, i)
+                .into_iter()
+                .map(|(sample, mut nanos)| {
+                    nanos.sort();
+                    Sample {
+                        path: sample.path.clone(),
+                        nanos: nanos[nanos.len() / 2],
+                        pixels: sample.pixels,
+                        raw_bytes: sample.raw_bytes,
+                        compressed_bytes: sample.compressed_bytes,
+                        allocations: sample.allocations,
+                        peak_rss_growth: sample.peak_rss_growth,
+                        output_buffer: sample.output_buffer,
+                        outcome: sample.outcome,
+                    }
+                })
+                .collect(),
+            wall_nanos: runs.iter().map(|r| r[i].wall_nanos).sum(),
+        })
+        .collect()
+}
+
+/// The unit throughput is reported in. Samples keep their raw counts, so results saved in one
+/// unit can be reported in any other.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Unit {
+    /// Megapixels per second
+    #[value(name = "mp")]
+    Megapixels,
+    /// Mebibytes of uncompressed pixel data per second
+    #[value(name = "mib")]
+    Mebibytes,
+    /// Megabytes (10^6 bytes) of uncompressed pixel data per second
+    #[value(name = "mb")]
+    Megabytes,
+    /// Nanoseconds per byte of uncompressed pixel data, where lower is faster
+    #[value(name = "ns-per-byte")]
+    NanosPerByte,
+    /// Files per second
+    #[value(name = "files")]
+    Files,
+}
+
+impl Unit {
+    /// Converts the time taken to process some number of files, pixels and bytes into this unit.
+    fn of(self, nanos: u128, pixels: u64, raw_bytes: u64, files: u64) -> f64 {
+        let seconds = nanos as f64 * 1e-9;
+        match self {
+            Unit::Megapixels => (pixels as f64 / (1 << 20) as f64) / seconds,
+            Unit::Mebibytes => (raw_bytes as f64 / (1 << 20) as f64) / seconds,
+            Unit::Megabytes => (raw_bytes as f64 / 1e6) / seconds,
+            Unit::NanosPerByte => nanos as f64 / raw_bytes as f64,
+            Unit::Files => files as f64 / seconds,
+        }
+    }
+
+    /// Converts a value in this unit into one that is higher for faster implementations, for
+    /// ranking them.
+    fn speed(self, value: f64) -> f64 {
+        match self {
+            Unit::NanosPerByte => 1.0 / value,
+            _ => value,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Unit::Megapixels => "MP/s",
+            Unit::Mebibytes => "MiB/s",
+            Unit::Megabytes => "MB/s",
+            Unit::NanosPerByte => "ns/B",
+            Unit::Files => "files/s",
+        }
+    }
+
+    /// Number of decimal places to print values in this unit with.
+    fn precision(self) -> usize {
+        match self {
+            Unit::NanosPerByte => 2,
+            _ => 1,
+        }
+    }
+
+    /// The other unit worth showing alongside this one, if any.
+    fn secondary(self) -> Option<Unit> {
+        match self {
+            Unit::Megapixels => Some(Unit::Mebibytes),
+            Unit::Mebibytes | Unit::Megabytes | Unit::NanosPerByte => Some(Unit::Megapixels),
+            Unit::Files => None,
+        }
+    }
+}
+
+/// The order to print implementations in
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SortOrder {
+    /// Fastest first
+    Speed,
+    /// Best compression ratio first
+    Ratio,
+    /// Alphabetically by name
+    Name,
+}
+
+/// A target workload to weight corpus categories by when averaging over them
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Profile {
+    /// Every category counts equally, however many files it has
+    Uniform,
+    /// Mostly photos and screenshots of web pages, as served on websites
+    Web,
+    /// Mostly textures and icons, as loaded by game engines
+    Games,
+}
+
+impl Profile {
+    /// Weights of the categories matching each prefix. Categories matching none are left out.
+    fn weights(self) -> &'static [(&'static str, f64)] {
+        match self {
+            Profile::Uniform => &[("", 1.0)],
+            Profile::Web => &[
+                ("photo_", 4.0),
+                ("screenshot_web", 3.0),
+                ("pngimg", 2.0),
+                ("icon_", 1.0),
+            ],
+            Profile::Games => &[
+                ("textures_", 4.0),
+                ("icon_", 2.0),
+                ("screenshot_game", 2.0),
+                ("pngimg", 1.0),
+            ],
+        }
+    }
+
+    fn weight(self, category: &str) -> Option<f64> {
+        self.weights()
+            .iter()
+            .find(|(prefix, _)| category.starts_with(prefix))
+            .map(|&(_, weight)| weight)
+    }
+}
+
+/// The category of a corpus file, which is the name of the directory it is in.
+fn category(path: &Path) -> String {
+    path.parent()
+        .and_then(Path::file_name)
+        .map_or(String::new(), |name| name.to_string_lossy().into_owned())
+}
+
+/// The geometric mean throughput of `measurement` over every category of the corpus, combined
+/// with the weights `profile` gives the categories rather than by how many files each has, or
+/// `None` if none of its files with a finite throughput are in the profile's categories.
+fn weighted_geomean(measurement: &Measurement, unit: Unit, profile: Profile) -> Option<f64> {
+    let mut log_throughputs: HashMap<String, Vec<f64>> = HashMap::new();
+    for sample in measurement.finite_samples(unit) {
+        log_throughputs
+            .entry(category(&sample.path))
+            .or_default()
+            .push(sample.throughput(unit).ln());
+    }
+
+    let (mut total, mut total_weight) = (0.0, 0.0);
+    for (category, logs) in &log_throughputs {
+        if let Some(weight) = profile.weight(category) {
+            total += weight * logs.iter().sum::<f64>() / logs.len() as f64;
+            total_weight += weight;
+        }
+    }
+    (total_weight > 0.0).then(|| (total / total_weight).exp())
+}
+
+/// Prints each implementation's geometric mean throughput over every category of the corpus,
+/// combined with the weights `profile` gives the categories rather than by how many files each
+/// has.
+pub fn print_weighted(results: &[Measurement], unit: Unit, profile: Profile) {
+    let width = name_width(results);
+    println!();
+    println!(
+        "Geomean {} weighted for the {:?} profile:",
+        unit.label(),
+        profile
+    );
+    for measurement in results {
+        let name = format!("{}:", measurement.name);
+        match weighted_geomean(measurement, unit, profile) {
+            Some(geomean) => println!("{:<width$} {:>10.*}", name, unit.precision(), geomean),
+            None => println!(
+                "{:<width$} no measurable files in the profile's categories",
+                name
+            ),
+        }
+    }
+}
+
+/// Sorts implementations into the given order, which otherwise defaults to the order they were
+/// measured in.
+pub fn sort(results: &mut [Measurement], order: SortOrder, unit: Unit) {
+    match order {
+        SortOrder::Speed => {
+            let speed = |m: &Measurement| unit.speed(m.throughput(unit));
+            results.sort_by(|a, b| speed(b).total_cmp(&speed(a)))
+        }
+        SortOrder::Ratio => {
+            results.sort_by(|a, b| a.compression_ratio().total_cmp(&b.compression_ratio()))
+        }
+        SortOrder::Name => results.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+}
+
+const GREEN: &str = "32";
+const DIM: &str = "2";
+const YELLOW: &str = "33";
+
+/// Whether to color output, which is only done when writing to a terminal and NO_COLOR is unset.
+fn use_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && std::io::stdout().is_terminal()
+}
+
+/// Wraps `text` in the ANSI escape sequence for the SGR parameter `code` if `color` is set.
+fn paint(text: String, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text
+    }
+}
+
+/// Width of the column holding implementation names, including the trailing colon.
+pub fn name_width(results: &[Measurement]) -> usize {
+    results.iter().map(|m| m.name.len() + 1).max().unwrap_or(0)
+}
+
+/// Prints the throughput of each implementation in both per-pixel and per-byte units where that
+/// makes sense, and its compression ratio if `show_ratio` is set. When coloring output, the
+/// fastest implementation and best compression ratio are highlighted and dominated encoders are
+/// dimmed.
+pub fn print_summary(results: &[Measurement], unit: Unit, show_ratio: bool) {
+    let width = name_width(results);
+    let color = use_color();
+    let fastest = results
+        .iter()
+        .map(|m| unit.speed(m.throughput(unit)))
+        .fold(f64::MIN, f64::max);
+    let best_ratio = results
+        .iter()
+        .map(|m| m.compression_ratio())
+        .fold(f64::MAX, f64::min);
+
+    for measurement in results {
+        let throughput = measurement.throughput(unit);
+        let speed = unit.speed(throughput);
+        let ratio = measurement.compression_ratio();
+        // An encoder is dominated if another one is at least as fast and compresses at least as
+        // well, and is strictly better at one of them.
+        let dominated = show_ratio
+            && results.iter().any(|other| {
+                let (t, r) = (
+                    unit.speed(other.throughput(unit)),
+                    other.compression_ratio(),
+                );
+                t >= speed && r <= ratio && (t > speed || r < ratio)
+            });
+        let highlight = |cell: String, best: bool| {
+            if best && !dominated {
+                paint(cell, GREEN, color)
+            } else {
+                cell
+            }
+        };
+
+        let name = format!("{}:", measurement.name);
+        let mut line = format!("{:<width$} ", name);
+        line += &highlight(
+            format!("{:>8.*} {}", unit.precision(), throughput, unit.label()),
+            speed == fastest,
+        );
+        if let Some(secondary) = unit.secondary() {
+            line += &format!(
+                " {:>8.*} {:<5}",
+                secondary.precision(),
+                measurement.throughput(secondary),
+                secondary.label()
+            );
+        }
+        if show_ratio {
+            line += " ";
+            line += &highlight(format!("{:>7.2}%", ratio * 100.0), ratio == best_ratio);
+        }
+        line += &format!("  ({} files)", measurement.samples.len());
+
+        if dominated {
+            line = paint(line, DIM, color);
+        }
+        println!("{}", line);
+    }
+}
+
+/// Prints the different ways of averaging throughput over the corpus for each implementation.
+pub fn print_aggregates(results: &[Measurement], unit: Unit) {
+    let width = name_width(results);
+    println!();
+    println!(
+        "{:<width$} {:>10} {:>10} {:>10} {:>14}",
+        format!("Per-file {}", unit.label()),
+        "mean",
+        "geomean",
+        "harmonic",
+        "bytes MiB/s"
+    );
+    for measurement in results {
+        let name = format!("{}:", measurement.name);
+        let Some((arithmetic, geometric, harmonic)) = measurement.mean_throughputs(unit) else {
+            println!("{:<width$} no files with a measurable time", name);
+            continue;
+        };
+        let precision = unit.precision();
+        println!(
+            "{:<width$} {:>10.*} {:>10.*} {:>10.*} {:>14.1}",
+            name,
+            precision,
+            arithmetic,
+            precision,
+            geometric,
+            precision,
+            harmonic,
+            measurement.throughput(Unit::Mebibytes)
+        );
+    }
+}
+
+/// Prints the file each implementation processed fastest and slowest, and how much its per-file
+/// throughput varied, so that a single pathological image stands out.
+pub fn print_spread(results: &[Measurement], unit: Unit) {
+    let width = name_width(results);
+
+    println!();
+    println!("Per-file spread ({}):", unit.label());
+    for measurement in results {
+        let by_speed = |a: &&Sample, b: &&Sample| {
+            unit.speed(a.throughput(unit))
+                .total_cmp(&unit.speed(b.throughput(unit)))
+        };
+        let (Some(fastest), Some(slowest)) = (
+            measurement.finite_samples(unit).max_by(by_speed),
+            measurement.finite_samples(unit).min_by(by_speed),
+        ) else {
+            continue;
+        };
+
+        let name = format!("{}:", measurement.name);
+        println!(
+            "{:<width$} CV {:>5.1}%  fastest {:.*} ({})  slowest {:.*} ({})",
+            name,
+            measurement.coefficient_of_variation(unit) * 100.0,
+            unit.precision(),
+            fastest.throughput(unit),
+            fastest.path.display(),
+            unit.precision(),
+            slowest.throughput(unit),
+            slowest.path.display()
+        );
+    }
+}
+
+/// Prints how much each implementation's throughput varied between repeated runs, and lists the
+/// files whose timings were least stable.
+pub fn print_stability(runs: &[Vec<Measurement>], unit: Unit) {
+    let width = name_width(&runs[0]);
+    let mut unstable = Vec::new();
+
+    println!();
+    println!("Stability over {} runs ({}):", runs.len(), unit.label());
+    for (i, measurement) in runs[0].iter().enumerate() {
+        let throughputs: Vec<f64> = runs.iter().map(|r| r[i].throughput(unit)).collect();
+        let mut unstable_files = 0;
+        let matched = matched_samples(runs, i);
+        for (sample, nanos) in &matched {
+            let nanos: Vec<f64> = nanos.iter().map(|&n| n as f64).collect();
+            let cv = stats::coefficient_of_variation(&nanos);
+            if cv > UNSTABLE_THRESHOLD {
+                unstable_files += 1;
+                unstable.push((cv, &measurement.name, &sample.path));
+            }
+        }
+
+        let name = format!("{}:", measurement.name);
+        println!(
+            "{:<width$} run-to-run CV {:>5.1}%  unstable files {}/{}",
+            name,
+            stats::coefficient_of_variation(&throughputs) * 100.0,
+            unstable_files,
+            matched.len()
+        );
+    }
+
+    if !unstable.is_empty() {
+        unstable.sort_by(|a, b| b.0.total_cmp(&a.0));
+        println!(
+            "Least stable files (run-to-run CV above {:.0}%):",
+            UNSTABLE_THRESHOLD * 100.0
+        );
+        for (cv, name, path) in unstable.iter().take(MAX_UNSTABLE_LISTED) {
+            println!("  {:>5.1}%  {}  {}", cv * 100.0, name, path.display());
+        }
+    }
+}
+
+/// Prints how much of the run's wall clock time went to each implementation, slowest first, both
+/// in total and in timed regions, and how much went to everything else, such as the harness
+/// itself. Shows where the time goes on long runs, and suggests which implementations to leave
+/// out of quick runs.
+pub fn print_time_spent(runs: &[Vec<Measurement>], wall_clock: Duration) {
+    let mut totals: Vec<(&str, f64, f64)> = Vec::new();
+    for measurement in runs.iter().flatten() {
+        let wall = measurement.wall_nanos as f64 * 1e-9;
+        let timed = measurement.total_nanos() as f64 * 1e-9;
+        match totals
+            .iter_mut()
+            .find(|(name, _, _)| *name == measurement.name)
+        {
+            Some((_, total_wall, total_timed)) => {
+                *total_wall += wall;
+                *total_timed += timed;
+            }
+            None => totals.push((&measurement.name, wall, timed)),
+        }
+    }
+    totals.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let wall_seconds = wall_clock.as_secs_f64();
+    let other = wall_seconds - totals.iter().map(|(_, wall, _)| wall).sum::<f64>();
+    let width = name_width(&runs[0]).max("everything else:".len());
+
+    println!();
+    println!(
+        "Time spent over {:.1} s of wall clock time (total, then timed):",
+        wall_seconds
+    );
+    for &(name, wall, timed) in &totals {
+        let name = format!("{}:", name);
+        println!(
+            "{:<width$} {:>8.1} s {:>5.1}%  {:>8.1} s",
+            name,
+            wall,
+            wall / wall_seconds * 100.0,
+            timed
+        );
+    }
+    let name = "everything else:";
+    println!(
+        "{:<width$} {:>8.1} s {:>5.1}%",
+        name,
+        other.max(0.0),
+        other.max(0.0) / wall_seconds * 100.0
+    );
+
+    // Implementations taking well over an even share of the run are the ones worth leaving out.
+    let even_share = wall_seconds / totals.len() as f64;
+    let slow: Vec<String> = totals
+        .iter()
+        .filter(|(_, wall, _)| *wall > SLOW_SHARE_FACTOR * even_share)
+        .map(|(name, wall, _)| format!("{} ({:.0}%)", name, wall / wall_seconds * 100.0))
+        .collect();
+    if !slow.is_empty() && slow.len() < totals.len() {
+        println!("For quicker runs, consider leaving out {}", slow.join(", "));
+    }
+}
+
+/// Prints the largest growth in resident set size that each implementation caused on any one file,
+/// if it could be measured.
+pub fn print_peak_rss(results: &[Measurement]) {
+    let peaks: Vec<Option<u64>> = results
+        .iter()
+        .map(|m| m.samples.iter().filter_map(|s| s.peak_rss_growth).max())
+        .collect();
+    if peaks.iter().all(Option::is_none) {
+        return;
+    }
+
+    let width = name_width(results);
+    println!();
+    println!("Peak RSS growth on any one file:");
+    for (measurement, peak) in results.iter().zip(peaks) {
+        let name = format!("{}:", measurement.name);
+        match peak {
+            Some(peak) => println!(
+                "{:<width$} {:>8.1} MiB",
+                name,
+                peak as f64 / (1 << 20) as f64
+            ),
+            None => println!("{:<width$} {:>8}", name, "n/a"),
+        }
+    }
+}
+
+/// Prints how much memory each implementation reserved for its output buffers beyond what the
+/// output needed, for the implementations whose buffers can be inspected.
+pub fn print_output_buffers(results: &[Measurement]) {
+    let buffers: Vec<(&Measurement, Vec<OutputBuffer>)> = results
+        .iter()
+        .map(|m| {
+            (
+                m,
+                m.samples.iter().filter_map(|s| s.output_buffer).collect(),
+            )
+        })
+        .filter(|(_, buffers): &(_, Vec<_>)| !buffers.is_empty())
+        .collect();
+    if buffers.is_empty() {
+        return;
+    }
+
+    let width = name_width(results);
+    println!();
+    println!("Output buffer capacity beyond the length used:");
+    for (measurement, buffers) in buffers {
+        let capacity: u64 = buffers.iter().map(|b| b.capacity_bytes).sum();
+        let len: u64 = buffers.iter().map(|b| b.len_bytes).sum();
+        let wasted = capacity.saturating_sub(len);
+        let name = format!("{}:", measurement.name);
+        println!(
+            "{:<width$} {:>8.2} MiB wasted ({:.1}% of output)",
+            name,
+            wasted as f64 / (1 << 20) as f64,
+            wasted as f64 / len as f64 * 100.0
+        );
+    }
+}
+
+/// Prints how many inputs each decoder accepted, rejected or panicked on at each truncation
+/// length, for measurements named after the decoder and the percentage of each file kept.
+pub fn print_truncation(results: &[Measurement]) {
+    let mut decoders: Vec<&str> = Vec::new();
+    let mut percents: Vec<&str> = Vec::new();
+    for measurement in results {
+        let Some((decoder, percent)) = measurement.name.rsplit_once(' ') else {
+            continue;
+        };
+        if !decoders.contains(&decoder) {
+            decoders.push(decoder);
+        }
+        if !percents.contains(&percent) {
+            percents.push(percent);
+        }
+    }
+
+    let width = decoders
+        .iter()
+        .map(|name| name.len() + 1)
+        .max()
+        .unwrap_or(0);
+    println!();
+    println!("Accepted/rejected/crashed by percentage of file kept:");
+    print!("{:<width$}", "");
+    for percent in &percents {
+        print!(" {:>14}", percent);
+    }
+    println!();
+    for decoder in decoders {
+        print!("{:<width$}", format!("{}:", decoder));
+        for percent in &percents {
+            let name = format!("{} {}", decoder, percent);
+            let count = |outcome| {
+                results
+                    .iter()
+                    .filter(|m| m.name == name)
+                    .flat_map(|m| &m.samples)
+                    .filter(|s| s.outcome == Some(outcome))
+                    .count()
+            };
+            let counts = format!(
+                "{}/{}/{}",
+                count(Outcome::Accepted),
+                count(Outcome::Rejected),
+                count(Outcome::Crashed)
+            );
+            print!(" {:>14}", counts);
+        }
+        println!();
+    }
+}
+
+/// Prints the compression ratio of each implementation and the total size of its output, for
+/// runs where nothing was timed.
+pub fn print_ratios(results: &[Measurement]) {
+    let width = name_width(results);
+    let color = use_color();
+    let best_ratio = results
+        .iter()
+        .map(|m| m.compression_ratio())
+        .fold(f64::MAX, f64::min);
+
+    for measurement in results {
+        let ratio = measurement.compression_ratio();
+        let compressed: u64 = measurement.samples.iter().map(|s| s.compressed_bytes).sum();
+        let name = format!("{}:", measurement.name);
+        let mut ratio_cell = format!("{:>7.2}%", ratio * 100.0);
+        if ratio == best_ratio {
+            ratio_cell = paint(ratio_cell, GREEN, color);
+        }
+        println!(
+            "{:<width$} {}  {:>12} bytes  ({} files)",
+            name,
+            ratio_cell,
+            compressed,
+            measurement.samples.len()
+        );
+    }
+}
+
+/// Prints how much smaller each implementation's output is than the original corpus files. Files
+/// that aren't in the corpus here, as when merging results from another machine, are left out.
+pub fn print_size_reduction(results: &[Measurement]) {
+    let width = name_width(results);
+
+    println!();
+    println!("Size reduction versus the original files:");
+    for measurement in results {
+        let (original, output) = measurement
+            .samples
+            .iter()
+            .filter_map(|s| Some((crate::archive::len(&s.path).ok()?, s.compressed_bytes)))
+            .fold((0, 0), |(original, output), (a, b)| {
+                (original + a, output + b)
+            });
+        let name = format!("{}:", measurement.name);
+        if original == 0 {
+            println!("{:<width$} none of the original files were found", name);
+            continue;
+        }
+        println!(
+            "{:<width$} {:>5.1}%  ({} -> {} bytes)",
+            name,
+            (1.0 - output as f64 / original as f64) * 100.0,
+            original,
+            output
+        );
+    }
+}
+
+/// Upper bounds of the latency histogram buckets in milliseconds, following a 1-2-5 series.
+const HISTOGRAM_BUCKETS_MS: [f64; 16] = [
+    0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0,
+];
+
+/// Upper bounds of the compression ratio histogram buckets in percent.
+const RATIO_BUCKETS_PERCENT: [f64; 12] = [
+    5.0, 10.0, 15.0, 20.0, 25.0, 30.0, 40.0, 50.0, 60.0, 75.0, 90.0, 100.0,
+];
+
+/// Prints percentiles of the per-file compression ratio of each encoder. Two encoders can have the
+/// same overall ratio with very different distributions, and a change that only helps some kinds
+/// of image shows up as movement in only some of the percentiles.
+pub fn print_ratio_distribution(results: &[Measurement]) {
+    let width = name_width(results);
+
+    println!();
+    println!("Per-file compression ratio (%):");
+    for measurement in results {
+        let ratios = measurement.compression_ratios();
+        if ratios.is_empty() {
+            continue;
+        }
+        let name = format!("{}:", measurement.name);
+        println!(
+            "{:<width$} min {:>6.2}  p10 {:>6.2}  p50 {:>6.2}  p90 {:>6.2}  max {:>6.2}",
+            name,
+            ratios[0] * 100.0,
+            stats::percentile(&ratios, 0.1) * 100.0,
+            stats::percentile(&ratios, 0.5) * 100.0,
+            stats::percentile(&ratios, 0.9) * 100.0,
+            ratios[ratios.len() - 1] * 100.0
+        );
+    }
+}
+
+/// Prints percentiles of the per-file latency of each implementation, for consumers that budget
+/// milliseconds per image rather than throughput.
+pub fn print_latency(results: &[Measurement]) {
+    let width = name_width(results);
+
+    println!();
+    println!("Per-file latency (ms):");
+    for measurement in results {
+        let millis = measurement.latencies_ms();
+        if millis.is_empty() {
+            continue;
+        }
+        let name = format!("{}:", measurement.name);
+        println!(
+            "{:<width$} p50 {:>9.3}  p90 {:>9.3}  p99 {:>9.3}  max {:>9.3}",
+            name,
+            stats::percentile(&millis, 0.5),
+            stats::percentile(&millis, 0.9),
+            stats::percentile(&millis, 0.99),
+            millis[millis.len() - 1]
+        );
+    }
+}
+
+/// Prints the time recorded for implementations that do nothing, which is included in every
+/// sample, and the sample length below which it makes up more than 1% of a sample.
+pub fn print_overhead(results: &[Measurement]) {
+    let width = name_width(results);
+
+    println!();
+    println!("Harness overhead per sample (ns):");
+    for measurement in results {
+        let nanos: Vec<f64> = measurement
+            .latencies_ms()
+            .iter()
+            .map(|millis| millis * 1e6)
+            .collect();
+        if nanos.is_empty() {
+            continue;
+        }
+        let median = stats::percentile(&nanos, 0.5);
+        let name = format!("{}:", measurement.name);
+        println!(
+            "{:<width$} p50 {:>7.0}  p99 {:>7.0}  max {:>7.0}  (over 1% of any sample under {:.1} us)",
+            name,
+            median,
+            stats::percentile(&nanos, 0.99),
+            nanos[nanos.len() - 1],
+            median * 100.0 / 1e3
+        );
+    }
+}
+
+/// Writes a CSV histogram of per-file values to `path`, with a row per bucket and a column per
+/// implementation. Each bucket counts the files at most its upper bound and above the previous
+/// bucket's, with a final unbounded bucket for anything larger.
+fn write_histogram(
+    results: &[Measurement],
+    path: &Path,
+    header: &str,
+    bounds: &[f64],
+    values: impl Fn(&Measurement) -> Vec<f64>,
+) {
+    let mut counts = vec![vec![0; results.len()]; bounds.len() + 1];
+    for (column, measurement) in results.iter().enumerate() {
+        for value in values(measurement) {
+            let bucket = bounds.partition_point(|&bound| bound < value);
+            counts[bucket][column] += 1;
+        }
+    }
+
+    let mut csv = String::from(header);
+    for measurement in results {
+        csv += &format!(",{}", measurement.name);
+    }
+    csv.push('\n');
+    for (bucket, row) in counts.iter().enumerate() {
+        match bounds.get(bucket) {
+            Some(bound) => csv += &bound.to_string(),
+            None => csv += "inf",
+        }
+        for count in row {
+            csv += &format!(",{}", count);
+        }
+        csv.push('\n');
+    }
+    std::fs::write(path, csv).unwrap();
+}
+
+/// Writes a CSV histogram of per-file latencies to `path`.
+pub fn write_latency_histogram(results: &[Measurement], path: &Path) {
+    write_histogram(
+        results,
+        path,
+        "upper_bound_ms",
+        &HISTOGRAM_BUCKETS_MS,
+        Measurement::latencies_ms,
+    );
+    println!("Wrote latency histogram to {}", path.display());
+}
+
+/// Writes a CSV histogram of per-file compression ratios, in percent, to `path`.
+pub fn write_ratio_histogram(results: &[Measurement], path: &Path) {
+    write_histogram(
+        results,
+        path,
+        "upper_bound_percent",
+        &RATIO_BUCKETS_PERCENT,
+        |m| m.compression_ratios().iter().map(|r| r * 100.0).collect(),
+    );
+    println!("Wrote compression ratio histogram to {}", path.display());
+}
+
+/// Classifies a change by `factor` as better or worse, or as no change if it isn't significant.
+/// Lower factors are better.
+fn classify_change(
+    factor: f64,
+    significant: bool,
+    better: &'static str,
+    worse: &'static str,
+) -> &'static str {
+    if !significant {
+        "unchanged"
+    } else if factor < 1.0 {
+        better
+    } else {
+        worse
+    }
+}
+
+/// Formats a change by `factor` as a percentage. Insignificant changes are dimmed when coloring
+/// output, and otherwise shown as zero so that noise doesn't read as a real change.
+fn format_delta(factor: f64, precision: usize, significant: bool, color: bool) -> String {
+    let delta = format!("{:>+7.*}%", precision, (factor - 1.0) * 100.0);
+    if significant {
+        delta
+    } else if color {
+        paint(delta, DIM, true)
+    } else {
+        format!("{:>7}%", "~0")
+    }
+}
+
+/// Prints how the overall time and, if `size_threshold` is given, the output size of each
+/// implementation changed from `baseline`. A change in time is only significant if it exceeds
+/// `time_threshold` percent and a Wilcoxon signed-rank test over the per-file changes gives a
+/// p-value below `alpha`. Getting faster at the cost of bigger output, or the reverse, is flagged
+/// as a trade-off rather than counted as an improvement.
+pub fn print_baseline_changes(
+    results: &[Measurement],
+    baseline: &[Measurement],
+    time_threshold: f64,
+    alpha: f64,
+    size_threshold: Option<f64>,
+) {
+    fn sizes(m: &Measurement) -> HashMap<&Path, u128> {
+        m.samples
+            .iter()
+            .map(|s| (s.path.as_path(), s.compressed_bytes as u128))
+            .collect()
+    }
+    let exceeds = |factor: f64, threshold: f64| (factor - 1.0).abs() * 100.0 > threshold;
+
+    let color = use_color();
+    let width = name_width(results);
+    println!();
+    println!("Changes versus the baseline:");
+    for measurement in results {
+        let Some(before) = baseline.iter().find(|m| m.name == measurement.name) else {
+            continue;
+        };
+        let before_timings = before.nanos_by_path();
+        let Some(time) = geomean_speedup(&before_timings, &measurement.nanos_by_path()) else {
+            continue;
+        };
+        let log_ratios: Vec<f64> = measurement
+            .samples
+            .iter()
+            .filter_map(|s| {
+                let before = *before_timings.get(s.path.as_path())?;
+                Some((s.nanos as f64 / before as f64).ln())
+            })
+            .collect();
+        let time_significant = exceeds(time, time_threshold)
+            && stats::wilcoxon_signed_rank(&log_ratios).is_some_and(|p| p < alpha);
+        let speed = classify_change(time, time_significant, "faster", "slower");
+        let time_delta = format_delta(time, 1, time_significant, color);
+
+        let name = format!("{}:", measurement.name);
+        let Some(size_threshold) = size_threshold else {
+            println!("{:<width$} time {} ({})", name, time_delta, speed);
+            continue;
+        };
+
+        let size = geomean_speedup(&sizes(before), &sizes(measurement)).unwrap_or(1.0);
+        let size_significant = exceeds(size, size_threshold);
+        let compression = classify_change(size, size_significant, "smaller", "bigger");
+        let verdict = match (speed, compression) {
+            ("slower", "smaller") | ("faster", "bigger") => "trade-off",
+            ("slower", _) | (_, "bigger") => "regression",
+            ("unchanged", "unchanged") => "no change",
+            _ => "improvement",
+        };
+        println!(
+            "{:<width$} time {} ({}), size {} ({}): {}",
+            name,
+            time_delta,
+            speed,
+            format_delta(size, 2, size_significant, color),
+            compression,
+            verdict
+        );
+    }
+}
+
+/// Prints how much slower each implementation has become than in `baseline` overall, and lists
+/// the `count` files it slowed down on the most along with their size and color type, to point
+/// at the kind of content a regression affects.
+pub fn print_regressions(results: &[Measurement], baseline: &[Measurement], count: usize) {
+    println!();
+    println!("Largest per-file slowdowns versus the baseline:");
+    for measurement in results {
+        let Some(before) = baseline.iter().find(|m| m.name == measurement.name) else {
+            println!("{}: not in the baseline", measurement.name);
+            continue;
+        };
+        let before = before.nanos_by_path();
+        let Some(overall) = geomean_speedup(&before, &measurement.nanos_by_path()) else {
+            continue;
+        };
+        println!("{}: {:.2}x as slow overall", measurement.name, overall);
+
+        let mut slowdowns: Vec<(f64, &Path)> = measurement
+            .samples
+            .iter()
+            .filter_map(|s| {
+                let before = *before.get(s.path.as_path())?;
+                Some((s.nanos as f64 / before as f64, s.path.as_path()))
+            })
+            .filter(|&(slowdown, _)| slowdown > 1.0)
+            .collect();
+        slowdowns.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (slowdown, path) in slowdowns.into_iter().take(count) {
+            let size = crate::archive::len(path).unwrap_or(0);
+            let color =
+                crate::color_type(path).map_or("unknown".to_owned(), |c| format!("{:?}", c));
+            println!(
+                "  {:>6.2}x  {} ({} bytes, {})",
+                slowdown,
+                path.display(),
+                size,
+                color
+            );
+        }
+    }
+}
+
+/// Prints the geomean per-file throughput and rank of each implementation measured both in this
+/// run on `arch` and in `other` on `other_arch`. Since absolute speeds differ between machines,
+/// only the rankings are compared, and every pair of implementations whose order flips between
+/// the architectures is listed.
+pub fn print_arch_comparison(
+    results: &[Measurement],
+    arch: &str,
+    other: &[Measurement],
+    other_arch: &str,
+    unit: Unit,
+) {
+    let (pairs, throughputs): (Vec<&Measurement>, Vec<(f64, f64)>) = results
+        .iter()
+        .filter_map(|m| {
+            let o = other.iter().find(|o| o.name == m.name)?;
+            Some((
+                m,
+                (m.mean_throughputs(unit)?.1, o.mean_throughputs(unit)?.1),
+            ))
+        })
+        .unzip();
+    let speeds: Vec<(f64, f64)> = throughputs
+        .iter()
+        .map(|&(here, there)| (unit.speed(here), unit.speed(there)))
+        .collect();
+    let rank = |value: f64, values: &mut dyn Iterator<Item = f64>| {
+        1 + values.filter(|&other| other > value).count()
+    };
+    let width = name_width(results);
+    let color = use_color();
+
+    println!();
+    println!(
+        "{:<width$} {:>16} {:>16}",
+        format!("Geomean {}", unit.label()),
+        arch,
+        other_arch
+    );
+    for (i, m) in pairs.iter().enumerate() {
+        let (here, there) = throughputs[i];
+        let here_rank = rank(speeds[i].0, &mut speeds.iter().map(|s| s.0));
+        let there_rank = rank(speeds[i].1, &mut speeds.iter().map(|s| s.1));
+        let line = format!(
+            "{:<width$} {:>10.*} (#{:<2}) {:>10.*} (#{:<2})",
+            format!("{}:", m.name),
+            unit.precision(),
+            here,
+            here_rank,
+            unit.precision(),
+            there,
+            there_rank
+        );
+        if here_rank != there_rank {
+            println!("{}", paint(line, YELLOW, color));
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    let mut flips = Vec::new();
+    for (i, a) in pairs.iter().enumerate() {
+        for (j, b) in pairs.iter().enumerate().skip(i + 1) {
+            let (a_here, a_there) = speeds[i];
+            let (b_here, b_there) = speeds[j];
+            if (a_here > b_here) != (a_there > b_there) {
+                let (faster, slower) = if a_here > b_here { (a, b) } else { (b, a) };
+                flips.push(format!(
+                    "  {} is faster than {} on {} but slower on {}",
+                    faster.name, slower.name, arch, other_arch
+                ));
+            }
+        }
+    }
+    if flips.is_empty() {
+        println!("The ranking is the same on both architectures");
+    } else {
+        println!("Rankings that flip between architectures:");
+        for flip in flips {
+            println!("{}", flip);
+        }
+    }
+}
+
+/// Prints how much longer each implementation took when run as `"<name> <variant>"` than as
+/// `"<name> <base>"`, as the geometric mean over the files both measured.
+pub fn print_variant_cost(results: &[Measurement], base: &str, variant: &str, heading: &str) {
+    let width = name_width(results);
+
+    println!();
+    println!("{}", heading);
+    for base_measurement in results {
+        let Some(name) = base_measurement
+            .name
+            .strip_suffix(base)
+            .and_then(|name| name.strip_suffix(' '))
+        else {
+            continue;
+        };
+        let variant_name = format!("{} {}", name, variant);
+        let Some(variant_measurement) = results.iter().find(|m| m.name == variant_name) else {
+            continue;
+        };
+        let Some(ratio) = geomean_speedup(
+            &base_measurement.nanos_by_path(),
+            &variant_measurement.nanos_by_path(),
+        ) else {
+            continue;
+        };
+        let name = format!("{}:", name);
+        println!("{:<width$} {:>+6.1}%", name, (ratio - 1.0) * 100.0);
+    }
+}
+
+/// Prints the median per-file latency of each implementation with warm and cold caches, and how
+/// much slower the cold runs were.
+pub fn print_cold(warm: &[Measurement], cold: &[Measurement]) {
+    let width = name_width(warm);
+    let median_ms = |m: &Measurement| stats::percentile(&m.latencies_ms(), 0.5);
+
+    println!();
+    println!("Median latency with warm and cold caches:");
+    for warm in warm {
+        let Some(cold) = cold.iter().find(|m| m.name == warm.name) else {
+            continue;
+        };
+        let name = format!("{}:", warm.name);
+        let slowdown = geomean_speedup(&warm.nanos_by_path(), &cold.nanos_by_path());
+        println!(
+            "{:<width$} warm {:>9.3} ms  cold {:>9.3} ms  {}",
+            name,
+            median_ms(warm),
+            median_ms(cold),
+            slowdown.map_or("-".to_owned(), |s| format!("{:.2}x slower", s))
+        );
+    }
+}
+
+/// Prints how many allocations and reallocations each implementation made per megapixel, and the
+/// most memory it allocated for any one file. Allocations made by C libraries are not counted.
+#[cfg(feature = "count-allocations")]
+pub fn print_allocations(results: &[Measurement]) {
+    let width = name_width(results);
+    println!();
+    println!(
+        "{:<width$} {:>12} {:>12} {:>12}",
+        "Allocations", "allocs/MP", "reallocs/MP", "peak MiB"
+    );
+    for measurement in results {
+        let stats: Vec<AllocationStats> = measurement
+            .samples
+            .iter()
+            .filter_map(|s| s.allocations)
+            .collect();
+        let pixels: u64 = measurement.samples.iter().map(|s| s.pixels).sum();
+        let megapixels = pixels as f64 / (1 << 20) as f64;
+        let allocations: u64 = stats.iter().map(|s| s.allocations).sum();
+        let reallocations: u64 = stats.iter().map(|s| s.reallocations).sum();
+        let peak = stats.iter().map(|s| s.peak_bytes).max().unwrap_or(0);
+
+        let name = format!("{}:", measurement.name);
+        println!(
+            "{:<width$} {:>12.1} {:>12.1} {:>12.1}",
+            name,
+            allocations as f64 / megapixels,
+            reallocations as f64 / megapixels,
+            peak as f64 / (1 << 20) as f64
+        );
+    }
+}
+
+/// Geometric mean over the files measured by both implementations of how many times faster `a`
+/// was than `b`, or `None` if they have no files in common. Files either one took no measurable
+/// time on are left out, as their ratio is zero or infinite.
+fn geomean_speedup(a: &HashMap<&Path, u128>, b: &HashMap<&Path, u128>) -> Option<f64> {
+    let log_ratios: Vec<f64> = a
+        .iter()
+        .filter_map(|(path, &a_nanos)| {
+            let b_nanos = *b.get(path)?;
+            if a_nanos == 0 || b_nanos == 0 {
+                return None;
+            }
+            Some((b_nanos as f64 / a_nanos as f64).ln())
+        })
+        .collect();
+
+    if log_ratios.is_empty() {
+        return None;
+    }
+    Some((log_ratios.iter().sum::<f64>() / log_ratios.len() as f64).exp())
+}
+
+/// Prints the results of timing `a` and `b` in pairs: the geomean and spread of how many times
+/// longer `b` took than `a` on each file, and whether the difference is significant.
+pub fn print_paired(a: &str, b: &str, ratios: &[f64], rounds: u32) {
+    if ratios.is_empty() {
+        println!("No files were encoded");
+        return;
+    }
+    let log_ratios: Vec<f64> = ratios.iter().map(|r| r.ln()).collect();
+    let geomean = (log_ratios.iter().sum::<f64>() / log_ratios.len() as f64).exp();
+    let mut sorted = ratios.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    println!(
+        "{} took {:.3}x as long as {} (geomean over {} files of the median of {} paired rounds)",
+        b,
+        geomean,
+        a,
+        ratios.len(),
+        rounds
+    );
+    println!(
+        "Per-file ratios: 10th percentile {:.3}x, median {:.3}x, 90th percentile {:.3}x",
+        stats::percentile(&sorted, 0.1),
+        stats::percentile(&sorted, 0.5),
+        stats::percentile(&sorted, 0.9)
+    );
+    println!(
+        "Wilcoxon signed-rank p = {}",
+        format_p_value(stats::wilcoxon_signed_rank(&log_ratios))
+    );
+}
+
+/// Prints the speedup of every implementation relative to every other one. Ratios are computed
+/// per file and then aggregated, rather than comparing aggregate throughputs.
+pub fn print_speedup_matrix(results: &[Measurement]) {
+    if results.len() < 2 {
+        return;
+    }
+
+    let timings: Vec<HashMap<&Path, u128>> = results
+        .iter()
+        .map(|m| {
+            m.samples
+                .iter()
+                .map(|s| (s.path.as_path(), s.nanos))
+                .collect()
+        })
+        .collect();
+
+    let width = name_width(results) + 4;
+    println!();
+    println!("Speedup of each row over each column (geometric mean of per-file ratios):");
+    let mut header = format!("{:<width$}", "");
+    for column in 1..=results.len() {
+        header += &format!(" {:>7}", format!("[{}]", column));
+    }
+    println!("{}", header);
+
+    for (row, measurement) in results.iter().enumerate() {
+        let mut line = format!("{:<width$}", format!("[{}] {}", row + 1, measurement.name));
+        for column in &timings {
+            match geomean_speedup(&timings[row], column) {
+                Some(speedup) => line += &format!(" {:>6.2}x", speedup),
+                None => line += &format!(" {:>7}", "-"),
+            }
+        }
+        println!("{}", line);
+    }
+}
+
+/// Formats an optional p-value for display.
+fn format_p_value(p: Option<f64>) -> String {
+    match p {
+        Some(p) => format!("{:.4}", p),
+        None => "n/a".to_owned(),
+    }
+}
+
+/// Prints p-values for the difference between the named reference implementation and each other
+/// implementation: a Wilcoxon signed-rank test on per-file paired timings, and a Mann-Whitney U
+/// test on the unpaired per-pixel timings.
+pub fn print_significance(results: &[Measurement], reference: &str) {
+    let Some(reference) = results.iter().find(|m| m.name == reference) else {
+        let names: Vec<&str> = results.iter().map(|m| m.name.as_str()).collect();
+        eprintln!(
+            "Unknown reference implementation '{}', expected one of: {}",
+            reference,
+            names.join(", ")
+        );
+        return;
+    };
+
+    let reference_timings: HashMap<&Path, u128> = reference
+        .samples
+        .iter()
+        .map(|s| (s.path.as_path(), s.nanos))
+        .collect();
+    let nanos_per_pixel = |m: &Measurement| -> Vec<f64> {
+        m.samples
+            .iter()
+            .map(|s| s.nanos as f64 / s.pixels as f64)
+            .collect()
+    };
+    let reference_per_pixel = nanos_per_pixel(reference);
+
+    let width = name_width(results);
+    println!();
+    println!("Significance of differences from {}:", reference.name);
+    for measurement in results {
+        if std::ptr::eq(measurement, reference) {
+            continue;
+        }
+
+        let log_ratios: Vec<f64> = measurement
+            .samples
+            .iter()
+            .filter_map(|s| {
+                let reference_nanos = *reference_timings.get(s.path.as_path())?;
+                Some((s.nanos as f64 / reference_nanos as f64).ln())
+            })
+            .collect();
+        let wilcoxon = stats::wilcoxon_signed_rank(&log_ratios);
+        let mann_whitney =
+            stats::mann_whitney_u(&nanos_per_pixel(measurement), &reference_per_pixel);
+
+        let name = format!("{}:", measurement.name);
+        println!(
+            "{:<width$} Wilcoxon p={:<8} Mann-Whitney p={}",
+            name,
+            format_p_value(wilcoxon),
+            format_p_value(mann_whitney)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmeasurable_files_are_left_out_of_speedups() {
+        let a = HashMap::from([
+            (Path::new("corpus/a.png"), 100),
+            (Path::new("corpus/b.png"), 0),
+            (Path::new("corpus/c.png"), 50),
+        ]);
+        let b = HashMap::from([
+            (Path::new("corpus/a.png"), 400),
+            (Path::new("corpus/b.png"), 30),
+            (Path::new("corpus/c.png"), 0),
+        ]);
+        assert_eq!(geomean_speedup(&a, &b), Some(4.0));
+        let none_measurable = HashMap::from([(Path::new("corpus/a.png"), 0)]);
+        assert_eq!(geomean_speedup(&a, &none_measurable), None);
+    }
+
+    #[test]
+    fn weighted_geomean_leaves_out_unmeasurable_files() {
+        // 1 MiB of pixels in 1 s and 4 s, and one file timed at 0 ns.
+        let measurement = Measurement::for_test(
+            "zune-png",
+            [
+                Sample::for_test("corpus/photo_kodak/a.png", 1_000_000_000, 1 << 20, 0),
+                Sample::for_test("corpus/icon_64/b.png", 4_000_000_000, 1 << 20, 0),
+                Sample::for_test("corpus/icon_64/c.png", 0, 1 << 20, 0),
+            ],
+        );
+        let uniform = weighted_geomean(&measurement, Unit::Mebibytes, Profile::Uniform).unwrap();
+        assert!((uniform - 0.5).abs() < 1e-9, "geomean = {}", uniform);
+        // Photos weigh 4 and icons 1 in the web profile.
+        let web = weighted_geomean(&measurement, Unit::Mebibytes, Profile::Web).unwrap();
+        assert!((web - 0.25f64.powf(0.2)).abs() < 1e-9, "geomean = {}", web);
+        assert_eq!(
+            weighted_geomean(&measurement, Unit::Mebibytes, Profile::Games),
+            Some(0.25)
+        );
+
+        let unmeasurable = Measurement::for_test(
+            "zune-png",
+            [Sample::for_test("corpus/icon_64/c.png", 0, 1 << 20, 0)],
+        );
+        assert_eq!(
+            weighted_geomean(&unmeasurable, Unit::Mebibytes, Profile::Uniform),
+            None
+        );
+    }
+}
@@ -0,0 +1,413 @@
+//! Files holding the per-file results of a run, so that runs split into shards across several
+//! machines or CI jobs can be combined later.
+//!
+//! Each line holds tab separated fields, starting with the kind of entry (shown here separated by
+//! spaces). `meta` entries describe the run, and `sample` entries each hold one timed file:
+//!
+//! ```text
+//! meta   mode    decode
+//! meta   corpus  qoi-bench
+//! meta   shard   1/4
+//! meta   machine <cpu model> (<hostname>)
+//! meta   simd    <arch> [<instruction sets detected at runtime>]
+//! meta   features [<optional Cargo features enabled>]
+//! meta   fingerprint <hash of every corpus file>
+//! sample <implementation> <nanos> <pixels> <raw bytes> <compressed bytes> <path>
+//! wall   <implementation> <nanos of wall clock time, including untimed setup>
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::results::{Measurement, Sample, Unit};
+
+/// Metadata that must match between results files for their timings to be comparable.
+const MACHINE_KEYS: [&str; 5] = ["machine", "build", "features", "simd", "fingerprint"];
+
+/// Description of the machine a run happened on, from its CPU model and hostname.
+pub fn machine() -> String {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let cpu = cpuinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("model name")?.split_once(':'))
+        .map_or("unknown CPU", |(_, model)| model.trim());
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+    format!("{} ({})", cpu, hostname.trim())
+}
+
+/// Writes `metadata` and every sample in `results` to `path`.
+pub fn write(path: &Path, metadata: &[(&str, String)], results: &[Measurement]) {
+    let mut contents = String::new();
+    for (key, value) in metadata {
+        contents += &format!("meta\t{}\t{}\n", key, value);
+    }
+    for measurement in results {
+        for sample in &measurement.samples {
+            contents += &format!(
+                "sample\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                measurement.name,
+                sample.nanos,
+                sample.pixels,
+                sample.raw_bytes,
+                sample.compressed_bytes,
+                sample.path.display()
+            );
+        }
+        if measurement.wall_nanos > 0 {
+            contents += &format!("wall\t{}\t{}\n", measurement.name, measurement.wall_nanos);
+        }
+    }
+    std::fs::write(path, contents).unwrap();
+    info!("Wrote results to {}", path.display());
+}
+
+/// Reads the metadata and measurements from the results file at `path`.
+pub fn read(path: &Path) -> (HashMap<String, String>, Vec<Measurement>) {
+    let contents = std::fs::read_to_string(path).unwrap();
+    let mut metadata = HashMap::new();
+    let mut results: Vec<Measurement> = Vec::new();
+    for line in contents.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            ["meta", key, value] => {
+                metadata.insert(key.to_owned(), value.to_owned());
+            }
+            ["sample", name, nanos, pixels, raw_bytes, compressed_bytes, file] => {
+                if results.last().is_none_or(|m| m.name != name) {
+                    results.push(Measurement::new(name));
+                }
+                results.last_mut().unwrap().samples.push(Sample {
+                    path: PathBuf::from(file),
+                    nanos: nanos.parse().unwrap(),
+                    pixels: pixels.parse().unwrap(),
+                    raw_bytes: raw_bytes.parse().unwrap(),
+                    compressed_bytes: compressed_bytes.parse().unwrap(),
+                    allocations: None,
+                    peak_rss_growth: None,
+                    output_buffer: None,
+                    outcome: None,
+                });
+            }
+            ["wall", name, nanos] => {
+                let index = match results.iter().position(|m| m.name == name) {
+                    Some(index) => index,
+                    None => {
+                        results.push(Measurement::new(name));
+                        results.len() - 1
+                    }
+                };
+                results[index].wall_nanos += nanos.parse::<u128>().unwrap();
+            }
+            _ => panic!("Malformed line in {}: {:?}", path.display(), line),
+        }
+    }
+    (metadata, results)
+}
+
+/// Checks that the results file at `path`, with `metadata`, can be compared with the current run.
+/// Comparing results measured on a different state of the corpus than `fingerprint` is refused
+/// unless `allow_drift` is set, since files that changed would show up as regressions. Each entry
+/// of `configuration` that differs from what the file records, such as the build settings, is
+/// annotated, as are the components whose `versions` differ, as they may explain changes.
+pub fn check_comparable(
+    path: &Path,
+    metadata: &HashMap<String, String>,
+    fingerprint: Option<&str>,
+    configuration: &[(&str, String)],
+    allow_drift: bool,
+) {
+    match (metadata.get("fingerprint"), fingerprint) {
+        (Some(theirs), Some(ours)) if theirs != ours => {
+            eprintln!(
+                "{} was measured on a different corpus (fingerprint {} rather than {}), so \
+                 changes may come from the files rather than the implementations",
+                path.display(),
+                theirs,
+                ours
+            );
+            if !allow_drift {
+                eprintln!("Pass --allow-corpus-drift to compare them anyway");
+                std::process::exit(1);
+            }
+        }
+        (None, Some(_)) => eprintln!(
+            "Warning: {} doesn't record which corpus it was measured on, so changes to the \
+             corpus since can't be detected",
+            path.display()
+        ),
+        _ => {}
+    }
+
+    for (key, ours) in configuration {
+        let Some(theirs) = metadata.get(*key) else {
+            continue;
+        };
+        if *key == "versions" {
+            let ours: HashSet<&str> = ours.split(", ").collect();
+            let changed: Vec<&str> = theirs
+                .split(", ")
+                .filter(|version| !ours.contains(version))
+                .collect();
+            if !changed.is_empty() {
+                eprintln!(
+                    "Note: {} was measured with {}, which differ from the versions built now",
+                    path.display(),
+                    changed.join(", ")
+                );
+            }
+        } else if theirs != ours {
+            eprintln!(
+                "Warning: {} was measured with {} {}, but this binary has {}",
+                path.display(),
+                key,
+                theirs,
+                ours
+            );
+        }
+    }
+}
+
+/// Reads the results files at `paths`, which must all be for `mode` and `corpus`, and combines
+/// their samples into one measurement per implementation. Results measured on different machines
+/// or builds aren't comparable, so combining them is refused unless `force` is set. Returns the
+/// measurements along with the unit and whether to show compression ratios, as recorded in the
+/// first file, and the metadata every file agrees on, to describe the combined results.
+pub fn merge(
+    paths: &[PathBuf],
+    mode: &str,
+    corpus: &str,
+    force: bool,
+) -> (Vec<Measurement>, Unit, bool, BTreeMap<String, String>) {
+    let mut results: Vec<Measurement> = Vec::new();
+    let mut first: Option<HashMap<String, String>> = None;
+    let mut common: Option<BTreeMap<String, String>> = None;
+    let mut seen = HashSet::new();
+    let mut duplicates = 0;
+
+    for path in paths {
+        let (metadata, measurements) = read(path);
+        for measurement in measurements {
+            let index = match results.iter().position(|m| m.name == measurement.name) {
+                Some(index) => index,
+                None => {
+                    results.push(Measurement::new(&measurement.name));
+                    results.len() - 1
+                }
+            };
+            results[index].wall_nanos += measurement.wall_nanos;
+            for sample in measurement.samples {
+                if seen.insert((measurement.name.clone(), sample.path.clone())) {
+                    results[index].samples.push(sample);
+                } else {
+                    duplicates += 1;
+                }
+            }
+        }
+
+        for (key, expected) in [("mode", mode), ("corpus", corpus)] {
+            if metadata.get(key).map(String::as_str) != Some(expected) {
+                eprintln!(
+                    "{} holds results for {} {:?}, not {:?}",
+                    path.display(),
+                    key,
+                    metadata.get(key).map_or("unknown", String::as_str),
+                    expected
+                );
+                std::process::exit(1);
+            }
+        }
+        match &mut common {
+            Some(common) => common.retain(|key, value| metadata.get(key) == Some(value)),
+            None => common = Some(metadata.clone().into_iter().collect()),
+        }
+        let first = first.get_or_insert_with(|| metadata.clone());
+        for key in MACHINE_KEYS {
+            if metadata.get(key) != first.get(key) {
+                eprintln!(
+                    "{} was measured with a different {} than {}: {:?} vs {:?}",
+                    path.display(),
+                    key,
+                    paths[0].display(),
+                    metadata.get(key).map_or("unknown", String::as_str),
+                    first.get(key).map_or("unknown", String::as_str)
+                );
+                if !force {
+                    eprintln!("Pass --force-merge to combine them anyway");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    if duplicates > 0 {
+        eprintln!(
+            "{} samples appeared in more than one results file; kept the first of each",
+            duplicates
+        );
+    }
+    let first = first.unwrap_or_default();
+    let unit = first
+        .get("unit")
+        .and_then(|unit| Unit::from_str(unit, false).ok())
+        .unwrap_or(Unit::Megapixels);
+    let show_ratio = first.get("ratio").is_some_and(|ratio| ratio == "true");
+    info!(
+        "Merged {} results files with {} implementations",
+        paths.len(),
+        results.len()
+    );
+    (results, unit, show_ratio, common.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+
+    #[test]
+    fn metadata_comes_before_samples() {
+        let results = [
+            Measurement::for_test(
+                "zune-qoi",
+                [Sample::for_test("corpus/a b.png", 1500, 4096, 1024)],
+            ),
+            Measurement::for_test(
+                "image-rs PNG",
+                [Sample::for_test("corpus/a b.png", 2500, 4096, 900)],
+            ),
+        ];
+        let file = TempFile::new("written.tsv");
+        write(file.path(), &[("mode", "encode".to_owned())], &results);
+
+        assert_eq!(
+            std::fs::read_to_string(file.path()).unwrap(),
+            "meta\tmode\tencode\n\
+             sample\tzune-qoi\t1500\t1024\t4096\t1024\tcorpus/a b.png\n\
+             sample\timage-rs PNG\t2500\t1024\t4096\t900\tcorpus/a b.png\n"
+        );
+    }
+
+    #[test]
+    fn results_survive_a_round_trip() {
+        let mut results = [
+            Measurement::for_test(
+                "zune-qoi",
+                [
+                    Sample::for_test("corpus/a.png", 1500, 4096, 1024),
+                    Sample::for_test("corpus/b c.png", 700, 400, 300),
+                ],
+            ),
+            Measurement::for_test(
+                "image-rs PNG",
+                [Sample::for_test("corpus/a.png", 2500, 4096, 900)],
+            ),
+        ];
+        results[0].wall_nanos = 9000;
+        let metadata = [("mode", "encode".to_owned()), ("seed", "7".to_owned())];
+        let file = TempFile::new("round-trip.tsv");
+        write(file.path(), &metadata, &results);
+        let (read_metadata, read_results) = read(file.path());
+
+        assert_eq!(read_metadata.len(), 2);
+        assert_eq!(read_metadata["mode"], "encode");
+        assert_eq!(read_metadata["seed"], "7");
+        assert_eq!(read_results.len(), 2);
+        assert_eq!(read_results[0].name, "zune-qoi");
+        assert_eq!(read_results[0].wall_nanos, 9000);
+        let sample = &read_results[0].samples[1];
+        assert_eq!(sample.path, Path::new("corpus/b c.png"));
+        assert_eq!(
+            (
+                sample.nanos,
+                sample.pixels,
+                sample.raw_bytes,
+                sample.compressed_bytes
+            ),
+            (700, 100, 400, 300)
+        );
+        assert_eq!(read_results[1].name, "image-rs PNG");
+        assert_eq!(read_results[1].samples.len(), 1);
+        assert_eq!(read_results[1].wall_nanos, 0);
+    }
+
+    /// Writes a shard of encode results for the qoi-bench corpus with the given extra metadata,
+    /// holding zune-qoi's time on each file.
+    fn write_shard(file: &TempFile, extra: &[(&str, &str)], times: &[(&str, u128)]) {
+        let mut metadata = vec![
+            ("mode", "encode".to_owned()),
+            ("corpus", "qoi-bench".to_owned()),
+            ("machine", "test machine".to_owned()),
+            ("unit", "files".to_owned()),
+            ("ratio", "true".to_owned()),
+        ];
+        metadata.extend(extra.iter().map(|&(key, value)| (key, value.to_owned())));
+        let samples = times
+            .iter()
+            .map(|&(path, nanos)| Sample::for_test(path, nanos, 400, 100));
+        let mut measurement = Measurement::for_test("zune-qoi", samples);
+        measurement.wall_nanos = 1000;
+        write(file.path(), &metadata, &[measurement]);
+    }
+
+    #[test]
+    fn merging_combines_shards() {
+        let shards = [TempFile::new("shard-1.tsv"), TempFile::new("shard-2.tsv")];
+        write_shard(
+            &shards[0],
+            &[("shard", "1/2"), ("seed", "3")],
+            &[("corpus/a.png", 10), ("corpus/c.png", 30)],
+        );
+        write_shard(
+            &shards[1],
+            &[("shard", "2/2"), ("seed", "3")],
+            &[("corpus/b.png", 20), ("corpus/a.png", 99)],
+        );
+        let paths: Vec<PathBuf> = shards.iter().map(|shard| shard.path().to_owned()).collect();
+        let (results, unit, show_ratio, metadata) = merge(&paths, "encode", "qoi-bench", false);
+
+        assert!(matches!(unit, Unit::Files));
+        assert!(show_ratio);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].wall_nanos, 2000);
+        // The second shard's copy of a.png is a duplicate, and the first one is kept.
+        let samples: Vec<(&Path, u128)> = results[0]
+            .samples
+            .iter()
+            .map(|s| (s.path.as_path(), s.nanos))
+            .collect();
+        assert_eq!(
+            samples,
+            [
+                (Path::new("corpus/a.png"), 10),
+                (Path::new("corpus/c.png"), 30),
+                (Path::new("corpus/b.png"), 20),
+            ]
+        );
+        // Only the metadata both shards agree on describes the merged results.
+        assert_eq!(metadata.get("seed").map(String::as_str), Some("3"));
+        assert_eq!(
+            metadata.get("machine").map(String::as_str),
+            Some("test machine")
+        );
+        assert!(!metadata.contains_key("shard"));
+    }
+
+    #[test]
+    fn wall_time_may_come_before_samples() {
+        let file = TempFile::new("wall-first.tsv");
+        std::fs::write(
+            file.path(),
+            "wall\tzune-qoi\t50\nsample\tzune-qoi\t10\t1\t4\t2\tcorpus/a.png\nwall\tzune-qoi\t5\n",
+        )
+        .unwrap();
+        let (_, results) = read(file.path());
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].samples.len(), 1);
+        assert_eq!(results[0].wall_nanos, 55);
+    }
+}
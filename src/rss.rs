@@ -0,0 +1,27 @@
+//! Resident set size measurements read from procfs. Unlike allocation counting these include
+//! memory used by C libraries. They are only available on Linux, and are `None` elsewhere.
+
+/// Reads a field reported in kB from /proc/self/status, returning it in bytes.
+fn status_field(name: &str) -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with(name))?;
+    let kilobytes: u64 = line[name.len()..]
+        .trim_start_matches(':')
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Resets the peak resident set size to the current one, and returns the current one.
+pub fn reset_peak() -> Option<u64> {
+    std::fs::write("/proc/self/clear_refs", "5").ok()?;
+    status_field("VmRSS")
+}
+
+/// The peak resident set size since the last call to `reset_peak`.
+pub fn peak() -> Option<u64> {
+    status_field("VmHWM")
+}
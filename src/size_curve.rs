@@ -0,0 +1,226 @@
+//! Fitting how each implementation's speed depends on image size, to say which one to use for
+//! small and for large images rather than only which is fastest over the whole corpus.
+//!
+//! The time taken on each file is modeled as a fixed cost plus a cost per byte of pixel data,
+//! fitted by least squares over the per-file samples.
+
+use std::path::Path;
+
+use crate::results::{name_width, Measurement};
+
+/// Number of sizes to evaluate the fitted curves at when writing them out.
+const CURVE_POINTS: usize = 24;
+
+/// A fitted model of the time taken on a file of `n` bytes of pixel data: `fixed + per_byte * n`
+/// nanoseconds.
+struct Fit {
+    fixed: f64,
+    per_byte: f64,
+}
+
+impl Fit {
+    /// Throughput in MiB/s predicted for a file with `bytes` bytes of pixel data.
+    fn throughput(&self, bytes: f64) -> f64 {
+        bytes / (1 << 20) as f64 / ((self.fixed + self.per_byte * bytes) * 1e-9)
+    }
+}
+
+/// Fits the time `measurement` took on each file to its size, or returns `None` if every file
+/// had the same size so that the two costs can't be told apart, or if no time was measured.
+fn fit(measurement: &Measurement) -> Option<Fit> {
+    let points: Vec<(f64, f64)> = measurement
+        .samples
+        .iter()
+        .map(|s| (s.raw_bytes as f64, s.nanos as f64))
+        .collect();
+    let n = points.len() as f64;
+    let mean_size = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_time = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let covariance: f64 = points
+        .iter()
+        .map(|(size, time)| (size - mean_size) * (time - mean_time))
+        .sum();
+    let variance: f64 = points
+        .iter()
+        .map(|(size, _)| (size - mean_size).powi(2))
+        .sum();
+    if variance == 0.0 {
+        return None;
+    }
+
+    let per_byte = covariance / variance;
+    let fixed = mean_time - per_byte * mean_size;
+    if fixed >= 0.0 && per_byte > 0.0 {
+        return Some(Fit { fixed, per_byte });
+    }
+    // A negative fixed cost or a per-byte cost that isn't positive is noise swamping one of the
+    // two costs; fit a line through the origin instead.
+    let per_byte = points.iter().map(|(size, time)| size * time).sum::<f64>()
+        / points.iter().map(|(size, _)| size * size).sum::<f64>();
+    if per_byte <= 0.0 {
+        return None;
+    }
+    Some(Fit {
+        fixed: 0.0,
+        per_byte,
+    })
+}
+
+/// Formats a size in bytes with a binary unit.
+fn format_size(bytes: f64) -> String {
+    if bytes >= (1 << 20) as f64 {
+        format!("{:.1} MiB", bytes / (1 << 20) as f64)
+    } else {
+        format!("{:.1} KiB", bytes / 1024.0)
+    }
+}
+
+/// Smallest and largest amount of pixel data in any file the implementations were measured on.
+fn size_range(results: &[Measurement]) -> (f64, f64) {
+    let sizes = results
+        .iter()
+        .flat_map(|m| &m.samples)
+        .map(|s| s.raw_bytes as f64);
+    (
+        sizes.clone().fold(f64::MAX, f64::min),
+        sizes.fold(0.0, f64::max),
+    )
+}
+
+/// Prints the fitted fixed and per-byte cost of each implementation, and the image sizes within
+/// the corpus's range at which one implementation overtakes another.
+pub fn print(results: &[Measurement]) {
+    let fits: Vec<(&str, Fit)> = results
+        .iter()
+        .filter_map(|m| Some((m.name.as_str(), fit(m)?)))
+        .collect();
+    let width = name_width(results);
+
+    println!();
+    println!("Time per file fitted to its size (fixed cost + streaming throughput):");
+    for (name, fit) in &fits {
+        let name = format!("{}:", name);
+        println!(
+            "{:<width$} {:>9.1} us + {:>8.1} MiB/s  (half speed below {})",
+            name,
+            fit.fixed * 1e-3,
+            1e9 / fit.per_byte / (1 << 20) as f64,
+            format_size(fit.fixed / fit.per_byte)
+        );
+    }
+
+    let (smallest, largest) = size_range(results);
+    let mut crossovers = Vec::new();
+    for (i, (a_name, a)) in fits.iter().enumerate() {
+        for (b_name, b) in &fits[i + 1..] {
+            let crossover = (b.fixed - a.fixed) / (a.per_byte - b.per_byte);
+            if !(smallest..=largest).contains(&crossover) {
+                continue;
+            }
+            // Above the crossover, the implementation with the lower per-byte cost is faster.
+            let (large, small) = if a.per_byte < b.per_byte {
+                (a_name, b_name)
+            } else {
+                (b_name, a_name)
+            };
+            crossovers.push((crossover, *large, *small));
+        }
+    }
+    crossovers.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if crossovers.is_empty() {
+        println!("No implementation overtakes another within the corpus's range of sizes");
+    }
+    for (size, large, small) in crossovers {
+        println!(
+            "  {} overtakes {} above {}",
+            large,
+            small,
+            format_size(size)
+        );
+    }
+}
+
+/// Writes the fitted throughput of each implementation at log-spaced sizes across the corpus's
+/// range to `path` as CSV, for plotting.
+pub fn write(results: &[Measurement], path: &Path) {
+    let fits: Vec<(&str, Fit)> = results
+        .iter()
+        .filter_map(|m| Some((m.name.as_str(), fit(m)?)))
+        .collect();
+    if fits.is_empty() {
+        eprintln!(
+            "No implementation's times could be fitted to image size, so {} wasn't written",
+            path.display()
+        );
+        return;
+    }
+    let (smallest, largest) = size_range(results);
+
+    let mut csv = String::from("size_bytes");
+    for (name, _) in &fits {
+        csv += &format!(",{} MiB/s", name);
+    }
+    csv.push('\n');
+    for i in 0..CURVE_POINTS {
+        let fraction = i as f64 / (CURVE_POINTS - 1) as f64;
+        let size = smallest * (largest / smallest).powf(fraction);
+        csv += &format!("{:.0}", size);
+        for (_, fit) in &fits {
+            csv += &format!(",{:.2}", fit.throughput(size));
+        }
+        csv.push('\n');
+    }
+    std::fs::write(path, csv).unwrap();
+    println!("Wrote fitted size curves to {}", path.display());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::Sample;
+
+    /// A measurement that took `nanos` on a file with `raw_bytes` bytes of pixel data, for each
+    /// pair.
+    fn measured(points: &[(u64, u128)]) -> Measurement {
+        let samples = points.iter().enumerate().map(|(i, &(raw_bytes, nanos))| {
+            Sample::for_test(&format!("corpus/{}.png", i), nanos, raw_bytes, 0)
+        });
+        Measurement::for_test("test", samples)
+    }
+
+    #[test]
+    fn fit_recovers_fixed_and_per_byte_costs() {
+        let fit = fit(&measured(&[(1000, 3000), (2000, 5000), (4000, 9000)])).unwrap();
+        assert!((fit.fixed - 1000.0).abs() < 1e-6, "fixed = {}", fit.fixed);
+        assert!(
+            (fit.per_byte - 2.0).abs() < 1e-9,
+            "per byte = {}",
+            fit.per_byte
+        );
+        // Half of the streaming throughput is reached where the fixed cost equals the rest.
+        let streaming = 1e9 / fit.per_byte / (1 << 20) as f64;
+        assert!((fit.throughput(500.0) / streaming - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn negative_fixed_cost_falls_back_to_a_line_through_the_origin() {
+        // Times grow faster than size, which a straight line fits with a negative intercept.
+        let fit = fit(&measured(&[(1000, 1000), (2000, 4000)])).unwrap();
+        assert_eq!(fit.fixed, 0.0);
+        // The least squares slope through the origin is (1e6 + 8e6) / (1e6 + 4e6).
+        assert!(
+            (fit.per_byte - 1.8).abs() < 1e-9,
+            "per byte = {}",
+            fit.per_byte
+        );
+    }
+
+    #[test]
+    fn unfittable_times_have_no_fit() {
+        assert!(fit(&measured(&[])).is_none());
+        // Every file has the same size.
+        assert!(fit(&measured(&[(1000, 10), (1000, 20)])).is_none());
+        // Nothing took any measurable time.
+        assert!(fit(&measured(&[(1000, 0), (2000, 0)])).is_none());
+    }
+}
@@ -0,0 +1,197 @@
+//! Statistical tests used to compare implementations.
+
+/// Population standard deviation of `values` divided by their mean, or zero if there are none or
+/// they are all zero.
+pub fn coefficient_of_variation(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.is_empty() || mean == 0.0 {
+        return 0.0;
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    variance.sqrt() / mean
+}
+
+/// The value at `fraction` of the way through `sorted`, interpolating linearly between
+/// neighboring values.
+pub fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let position = fraction * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Returns the 1-based ranks of `values`, giving tied values the average of their ranks.
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end + 1) as f64 / 2.0;
+        for &i in &order[start..end] {
+            ranks[i] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// Sum of `t^3 - t` over every group of `t` tied values, used to correct rank test variances.
+fn tie_correction(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted
+        .chunk_by(|a, b| a == b)
+        .map(|group| {
+            let t = group.len() as f64;
+            t * t * t - t
+        })
+        .sum()
+}
+
+/// Complementary error function, accurate to about 1.2e-7 (Numerical Recipes' `erfcc`).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+/// Two-sided p-value of a standard normal test statistic.
+fn two_sided_p(z: f64) -> f64 {
+    erfc(z.abs() / std::f64::consts::SQRT_2)
+}
+
+/// Wilcoxon signed-rank test of whether paired `differences` are centered on zero, using the
+/// normal approximation. Returns the two-sided p-value, or `None` if every difference is zero.
+pub fn wilcoxon_signed_rank(differences: &[f64]) -> Option<f64> {
+    let nonzero: Vec<f64> = differences.iter().copied().filter(|&d| d != 0.0).collect();
+    if nonzero.is_empty() {
+        return None;
+    }
+
+    let magnitudes: Vec<f64> = nonzero.iter().map(|d| d.abs()).collect();
+    let ranks = ranks(&magnitudes);
+    let positive_rank_sum: f64 = nonzero
+        .iter()
+        .zip(&ranks)
+        .filter(|(&d, _)| d > 0.0)
+        .map(|(_, &rank)| rank)
+        .sum();
+
+    let n = nonzero.len() as f64;
+    let mean = n * (n + 1.0) / 4.0;
+    let variance = n * (n + 1.0) * (2.0 * n + 1.0) / 24.0 - tie_correction(&magnitudes) / 48.0;
+    if variance <= 0.0 {
+        return None;
+    }
+
+    let deviation = ((positive_rank_sum - mean).abs() - 0.5).max(0.0);
+    Some(two_sided_p(deviation / variance.sqrt()))
+}
+
+/// Mann-Whitney U test of whether samples `a` and `b` come from the same distribution, using the
+/// normal approximation. Returns the two-sided p-value, or `None` if either sample is empty.
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+
+    let combined: Vec<f64> = a.iter().chain(b).copied().collect();
+    let ranks = ranks(&combined);
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    let u = ranks[..a.len()].iter().sum::<f64>() - n1 * (n1 + 1.0) / 2.0;
+
+    let n = n1 + n2;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_correction(&combined) / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return None;
+    }
+
+    let deviation = ((u - mean).abs() - 0.5).max(0.0);
+    Some(two_sided_p(deviation / variance.sqrt()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ties_share_their_average_rank() {
+        assert_eq!(ranks(&[3.0, 1.0, 3.0, 2.0]), [3.5, 1.0, 3.5, 2.0]);
+        assert_eq!(tie_correction(&[3.0, 1.0, 3.0, 2.0]), 6.0);
+    }
+
+    #[test]
+    fn wilcoxon_detects_a_consistent_shift() {
+        // Ten positive differences give W+ = 55 against an expected 27.5 with a variance of
+        // 96.25, so z = (27.5 - 0.5) / 9.81 = 2.75 after the continuity correction.
+        let differences: Vec<f64> = (1..=10).map(f64::from).collect();
+        let p = wilcoxon_signed_rank(&differences).unwrap();
+        assert!((p - 0.00592).abs() < 1e-4, "p = {}", p);
+    }
+
+    #[test]
+    fn wilcoxon_accepts_differences_centered_on_zero() {
+        let p = wilcoxon_signed_rank(&[-3.0, -2.0, -1.0, 1.0, 2.0, 3.0]).unwrap();
+        assert!(p > 0.9, "p = {}", p);
+        // Zero differences are dropped before ranking.
+        assert_eq!(
+            wilcoxon_signed_rank(&[0.0, -3.0, -2.0, -1.0, 1.0, 2.0, 3.0]),
+            Some(p)
+        );
+        assert_eq!(wilcoxon_signed_rank(&[0.0, 0.0]), None);
+        assert_eq!(wilcoxon_signed_rank(&[]), None);
+    }
+
+    #[test]
+    fn mann_whitney_separates_disjoint_samples() {
+        let a: Vec<f64> = (0..10).map(f64::from).collect();
+        let b: Vec<f64> = (10..20).map(f64::from).collect();
+        assert!(mann_whitney_u(&a, &b).unwrap() < 0.001);
+        assert!(mann_whitney_u(&a, &a).unwrap() > 0.9);
+        assert_eq!(mann_whitney_u(&a, &[]), None);
+        assert_eq!(mann_whitney_u(&[1.0, 1.0], &[1.0]), None);
+    }
+
+    #[test]
+    fn percentiles_interpolate_between_neighbors() {
+        let sorted = [1.0, 2.0, 4.0, 8.0, 16.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 0.5), 4.0);
+        assert_eq!(percentile(&sorted, 0.625), 6.0);
+        assert_eq!(percentile(&sorted, 1.0), 16.0);
+        assert_eq!(percentile(&[7.0], 0.9), 7.0);
+        assert!(percentile(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn coefficient_of_variation_is_relative_to_the_mean() {
+        assert_eq!(coefficient_of_variation(&[1.0, 3.0]), 0.5);
+        assert_eq!(coefficient_of_variation(&[10.0, 30.0]), 0.5);
+        assert_eq!(coefficient_of_variation(&[]), 0.0);
+        assert_eq!(coefficient_of_variation(&[0.0, 0.0]), 0.0);
+    }
+}
@@ -0,0 +1,53 @@
+//! Helpers shared by the unit tests.
+
+use std::path::{Path, PathBuf};
+
+use crate::results::{Measurement, Sample};
+
+impl Sample {
+    /// A sample of 8-bit RGBA pixel data, with only the fields that results files hold set.
+    pub fn for_test(path: &str, nanos: u128, raw_bytes: u64, compressed_bytes: u64) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            nanos,
+            pixels: raw_bytes / 4,
+            raw_bytes,
+            compressed_bytes,
+            allocations: None,
+            peak_rss_growth: None,
+            output_buffer: None,
+            outcome: None,
+        }
+    }
+}
+
+impl Measurement {
+    /// A measurement named `name` holding `samples`.
+    pub fn for_test(name: &str, samples: impl IntoIterator<Item = Sample>) -> Self {
+        let mut measurement = Measurement::new(name);
+        measurement.samples.extend(samples);
+        measurement
+    }
+}
+
+/// A path in the temporary directory that no other test or process uses, whose file is removed
+/// when this is dropped.
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str) -> Self {
+        let file = format!("corpus-bench-{}-{}", std::process::id(), name);
+        Self(std::env::temp_dir().join(file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // Nothing was written if the test failed early.
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    process::{exit, Command, ExitStatus},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+const USAGE: &str = "\
+Usage: cargo xtask <TASK>
+
+Tasks:
+  setup [--from-tar <PATH>]  Download the benchmark corpora into ./corpus, or extract the QOI
+                             suite from a local tarball
+  generate-synthetic <ARGS>  Generate images with controlled content into ./corpus/synthetic,
+                             with --frames <N> frame sequences into ./corpus/frames, and with
+                             --hdr float OpenEXR images into ./corpus/hdr
+  bench <ARGS>...            Build corpus-bench in release mode and run it with ARGS
+  bench --baseline <REV> <ARGS>...
+                             Run the benchmark with ARGS on the git revision REV and then on the
+                             working tree, and report what changed between the two
+  report <MODE> <CORPUS> <RESULTS>...
+                             Combine results saved by --results into one report
+  watch <CRATE> <ARGS>...    Rerun a fast bench with ARGS whenever a source file under CRATE (a
+                             dependency patched to a local checkout) changes, and print how each
+                             implementation's throughput moved";
+
+/// How often the watched crate is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where `bench --baseline` checks out the baseline revision
+const BASELINE_WORKTREE: &str = "target/xtask-baseline";
+
+/// Where `bench --baseline` saves the baseline revision's results
+const BASELINE_RESULTS: &str = "target/xtask-baseline-results.tsv";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let task = args.next();
+    let root = workspace_root();
+
+    let status = match task.as_deref() {
+        Some("setup") => {
+            let mut command = Command::new("sh");
+            command.arg(root.join("download.sh")).current_dir(&root);
+            match (args.next().as_deref(), args.next()) {
+                (None, _) => {}
+                (Some("--from-tar"), Some(path)) => {
+                    command.env("QOI_BENCHMARK_TAR", std::path::absolute(path).unwrap());
+                }
+                _ => {
+                    eprintln!("{USAGE}");
+                    exit(2);
+                }
+            }
+            command.status()
+        }
+        Some("generate-synthetic") => Command::new(cargo())
+            .args(["run", "--release", "--bin", "generate-synthetic", "--"])
+            .args(args)
+            .current_dir(&root)
+            .status(),
+        Some("watch") => {
+            let Some(watched) = args.next() else {
+                eprintln!("{USAGE}");
+                exit(2);
+            };
+            watch(&root, Path::new(&watched), &args.collect::<Vec<_>>())
+        }
+        Some("bench") => {
+            let mut args: Vec<String> = args.collect();
+            if args.first().map(String::as_str) == Some("--baseline") {
+                let Some(revision) = args.get(1).cloned() else {
+                    eprintln!("{USAGE}");
+                    exit(2);
+                };
+                args.drain(..2);
+                bench_against(&root, &revision, &args)
+            } else {
+                bench(&root, &root, &args).status()
+            }
+        }
+        Some("report") => {
+            let args: Vec<String> = args.collect();
+            let [mode, corpus, results @ ..] = &args[..] else {
+                eprintln!("{USAGE}");
+                exit(2);
+            };
+            if results.is_empty() {
+                eprintln!("{USAGE}");
+                exit(2);
+            }
+            bench(&root, &root, &[mode, corpus])
+                .arg("--merge")
+                .args(results)
+                .status()
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            exit(2);
+        }
+    };
+
+    let status = status.unwrap();
+    if !status.success() {
+        exit(status.code().unwrap_or(1));
+    }
+}
+
+/// A command that builds corpus-bench from the `source` checkout in release mode and runs it with
+/// `args` from `root`, so that the corpus is found in the same place for every checkout.
+fn bench(root: &Path, source: &Path, args: &[impl AsRef<OsStr>]) -> Command {
+    let mut command = Command::new(cargo());
+    command
+        .args([
+            "run",
+            "--release",
+            "--package",
+            "corpus-bench",
+            "--manifest-path",
+        ])
+        .arg(source.join("Cargo.toml"))
+        .arg("--")
+        .args(args)
+        .current_dir(root);
+    command
+}
+
+/// Runs the benchmark on `revision` checked out into a separate worktree and saves its results,
+/// then runs it on the working tree with those results as the baseline.
+fn bench_against(root: &Path, revision: &str, args: &[String]) -> io::Result<ExitStatus> {
+    let worktree = root.join(BASELINE_WORKTREE);
+    let status = if worktree.exists() {
+        Command::new("git")
+            .args(["checkout", "--quiet", "--detach", revision])
+            .current_dir(&worktree)
+            .status()?
+    } else {
+        Command::new("git")
+            .args(["worktree", "add", "--quiet", "--detach"])
+            .arg(&worktree)
+            .arg(revision)
+            .current_dir(root)
+            .status()?
+    };
+    if !status.success() {
+        return Ok(status);
+    }
+    // Cargo.lock isn't checked in, so pin the baseline to the same dependency versions as the
+    // working tree and only the benchmark's own code differs.
+    let lock = root.join("Cargo.lock");
+    if lock.exists() {
+        std::fs::copy(&lock, worktree.join("Cargo.lock"))?;
+    }
+
+    println!("Benchmarking {}", revision);
+    let results = root.join(BASELINE_RESULTS);
+    let status = bench(root, &worktree, args)
+        .arg("--results")
+        .arg(&results)
+        .status()?;
+    if !status.success() {
+        return Ok(status);
+    }
+
+    println!("Benchmarking the working tree");
+    bench(root, root, args)
+        .arg("--baseline")
+        .arg(&results)
+        .status()
+}
+
+/// Reruns the benchmark each time a file under `watched` is modified, until interrupted.
+fn watch(root: &Path, watched: &Path, args: &[String]) -> ! {
+    let results = root.join("target/watch-results.tsv");
+    let mut args = args.to_vec();
+    if !args.iter().any(|arg| arg == "--fast") {
+        args.push("--fast".into());
+    }
+    let mut last_modified = None;
+    let mut previous: Option<BTreeMap<String, f64>> = None;
+    loop {
+        let modified = latest_modification(watched);
+        if last_modified == Some(modified) {
+            thread::sleep(WATCH_INTERVAL);
+            continue;
+        }
+        last_modified = Some(modified);
+
+        let status = Command::new(cargo())
+            .args(["run", "--release", "--package", "corpus-bench", "--"])
+            .args(&args)
+            .args(["--quiet", "--results"])
+            .arg(&results)
+            .current_dir(root)
+            .status()
+            .unwrap();
+        if !status.success() {
+            eprintln!("Benchmark failed, waiting for the next change");
+            continue;
+        }
+
+        let current = throughputs(&results);
+        if let Some(previous) = &previous {
+            println!("Change since the previous iteration:");
+            for (name, throughput) in &current {
+                match previous.get(name) {
+                    Some(before) => {
+                        println!("  {}: {:+.1}%", name, (throughput / before - 1.0) * 100.0)
+                    }
+                    None => println!("  {}: new", name),
+                }
+            }
+        }
+        previous = Some(current);
+        println!("Watching {} for changes", watched.display());
+    }
+}
+
+/// The most recent modification time of any file under `path`, skipping build output and version
+/// control directories.
+fn latest_modification(path: &Path) -> SystemTime {
+    let metadata = std::fs::metadata(path).unwrap();
+    if !metadata.is_dir() {
+        return metadata.modified().unwrap();
+    }
+    std::fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !path.ends_with("target") && !path.ends_with(".git"))
+        .map(|path| latest_modification(&path))
+        .fold(metadata.modified().unwrap(), SystemTime::max)
+}
+
+/// Overall pixels per nanosecond of each implementation in a corpus-bench results file.
+fn throughputs(results: &Path) -> BTreeMap<String, f64> {
+    let mut totals: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+    for line in std::fs::read_to_string(results).unwrap().lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let ["sample", name, nanos, pixels, ..] = fields[..] {
+            let total = totals.entry(name.to_owned()).or_default();
+            total.0 += pixels.parse::<f64>().unwrap();
+            total.1 += nanos.parse::<f64>().unwrap();
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(name, (pixels, nanos))| (name, pixels / nanos))
+        .collect()
+}
+
+fn cargo() -> String {
+    std::env::var("CARGO").unwrap_or("cargo".into())
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_owned()
+}